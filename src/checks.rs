@@ -0,0 +1,1326 @@
+// A safer version of cargo publish
+//
+// Copyright (C) 2025 Georg Semmler
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see
+// <https://www.gnu.org/licenses/>.
+
+use colored::Colorize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::registry;
+use crate::report::VerificationReport;
+
+/// Returns false if the version to publish is not greater than all already published versions
+pub(crate) fn check_version_increases(
+    registry: &registry::Registry,
+    package_name: &str,
+    package_version: &cargo_metadata::semver::Version,
+) -> bool {
+    let Some(latest) = registry.published_versions(package_name).into_iter().max() else {
+        // the first version of a crate is always fine
+        return true;
+    };
+    if *package_version > latest {
+        return true;
+    }
+    eprintln!(
+        "{}: the version to publish ({}) is not greater than the latest published version ({}) of `{package_name}`. \
+         Pass `--allow-nonmonotonic` if that is intended, e.g. for backports",
+        "error".red().bold(),
+        package_version.to_string().bold(),
+        latest.to_string().bold(),
+    );
+    false
+}
+
+/// Warn about crates in the registry whose name only differs from the name of the package
+/// by case or by `-` vs `_`
+///
+/// Only checks crates that were never published before unless `is_forced` is set
+pub(crate) fn check_name_collision(
+    registry: &registry::Registry,
+    package_name: &str,
+    is_forced: bool,
+) {
+    let mut names = registry.crate_names(package_name);
+    if names.iter().any(|name| name == package_name) && !is_forced {
+        return;
+    }
+    for variant in [
+        package_name.replace('-', "_"),
+        package_name.replace('_', "-"),
+    ] {
+        if variant != package_name {
+            names.extend(registry.crate_names(&variant));
+        }
+    }
+    let similar = names
+        .into_iter()
+        .filter(|name| name != package_name)
+        .collect::<BTreeSet<_>>();
+    if similar.is_empty() {
+        return;
+    }
+    eprintln!(
+        "{}: the registry `{}` already contains {} whose name only differs from `{package_name}` \
+         by case or by `-` vs `_`. Registries usually treat these names as equal, \
+         so publishing `{package_name}` might fail or confuse users",
+        "warning".yellow(),
+        registry.name,
+        similar
+            .iter()
+            .map(|name| format!("`{name}`"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
+/// Returns false if the edition of the packaged `Cargo.toml` differs from the effective edition
+/// of the package
+///
+/// Cargo writes an inherited or defaulted edition explicitly into the packaged manifest,
+/// so the local manifest might not contain the same value
+pub(crate) fn check_manifest_edition(package: &cargo_metadata::Package, manifest: &str) -> bool {
+    // an unparsable manifest is reported by the checks that need its content
+    let Ok(manifest) = manifest.parse::<toml::Table>() else {
+        return true;
+    };
+    // cargo uses 2015 for manifests without an edition
+    let published = manifest
+        .get("package")
+        .and_then(|p| p.get("edition"))
+        .and_then(|e| e.as_str())
+        .unwrap_or("2015");
+    if published == package.edition.as_str() {
+        return true;
+    }
+    eprintln!(
+        "{}: the packaged `Cargo.toml` uses the edition {published}, \
+         but the effective edition of `{}` is {}",
+        "error".red().bold(),
+        package.name,
+        package.edition.as_str()
+    );
+    false
+}
+
+/// Returns false if a dependency of the packaged `Cargo.toml` does not match any version
+/// published to the registry or only matches yanked versions
+///
+/// Dependencies from other registries are skipped
+pub(crate) fn check_manifest_dependencies(registry: &registry::Registry, manifest: &str) -> bool {
+    let manifest = match manifest.parse::<toml::Table>() {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!(
+                "{}: failed to parse the packaged `Cargo.toml`: {e}",
+                "error".red().bold()
+            );
+            return false;
+        }
+    };
+    let kinds = ["dependencies", "dev-dependencies", "build-dependencies"];
+    let targets = manifest
+        .get("target")
+        .and_then(|t| t.as_table())
+        .into_iter()
+        .flat_map(|targets| targets.values())
+        .filter_map(|target| target.as_table());
+    let dependencies = std::iter::once(&manifest)
+        .chain(targets)
+        .flat_map(|table| kinds.iter().filter_map(|kind| table.get(*kind)))
+        .filter_map(|dependencies| dependencies.as_table())
+        .flatten();
+    let normalize_index = |index: &str| {
+        index
+            .trim_start_matches("sparse+")
+            .trim_end_matches('/')
+            .to_owned()
+    };
+    let registry_index = normalize_index(&registry.index);
+
+    let mut is_resolvable = true;
+    let mut checked = BTreeSet::new();
+    for (key, dependency) in dependencies {
+        let (name, requirement, index) = match dependency {
+            toml::Value::String(requirement) => (key.as_str(), requirement.as_str(), None),
+            toml::Value::Table(dependency) => {
+                let Some(requirement) = dependency.get("version").and_then(|v| v.as_str()) else {
+                    continue;
+                };
+                let name = dependency
+                    .get("package")
+                    .and_then(|p| p.as_str())
+                    .unwrap_or(key);
+                let index = dependency.get("registry-index").and_then(|i| i.as_str());
+                (name, requirement, index)
+            }
+            _ => continue,
+        };
+        // cargo only records the index of dependencies that are not from crates.io
+        let is_same_registry = match index {
+            Some(index) => normalize_index(index) == registry_index,
+            None => registry.name == registry::CRATES_IO,
+        };
+        if !is_same_registry {
+            eprintln!(
+                "{}: skipping `{name}`, which is not from the registry `{}`",
+                "note".cyan(),
+                registry.name
+            );
+            continue;
+        }
+        if !checked.insert((name, requirement)) {
+            continue;
+        }
+        let Ok(version_req) = cargo_metadata::semver::VersionReq::parse(requirement) else {
+            eprintln!(
+                "{}: the dependency `{name}` has the invalid version requirement `{requirement}`",
+                "error".red().bold()
+            );
+            is_resolvable = false;
+            continue;
+        };
+        let matching = registry
+            .published_versions_with_yanked(name)
+            .into_iter()
+            .filter(|(version, _)| version_req.matches(version))
+            .collect::<Vec<_>>();
+        if matching.is_empty() {
+            eprintln!(
+                "{}: the dependency `{name} {requirement}` does not match any version published to `{}`",
+                "error".red().bold(),
+                registry.name
+            );
+            is_resolvable = false;
+        } else if matching.iter().all(|(_, is_yanked)| *is_yanked) {
+            eprintln!(
+                "{}: the dependency `{name} {requirement}` only matches yanked versions",
+                "error".red().bold()
+            );
+            is_resolvable = false;
+        }
+    }
+    is_resolvable
+}
+
+/// Returns false if any publishable workspace member has a different version than the package to publish
+pub(crate) fn check_workspace_version_sync(
+    metadata: &cargo_metadata::Metadata,
+    package_to_publish: &cargo_metadata::Package,
+) -> bool {
+    let mismatches = metadata
+        .workspace_packages()
+        .into_iter()
+        .filter(|p| is_publishable(p))
+        .filter(|p| p.version != package_to_publish.version)
+        .collect::<Vec<_>>();
+    if mismatches.is_empty() {
+        return true;
+    }
+    eprintln!(
+        "{}: the following workspace members do not share the version {} of `{}`:",
+        "error".red().bold(),
+        package_to_publish.version,
+        package_to_publish.name
+    );
+    eprintln!();
+    for package in mismatches {
+        eprintln!("{} ({})", package.name.as_str().bold(), package.version);
+    }
+    false
+}
+
+/// Report dependencies with a `*` version requirement, without a version requirement
+/// or with a requirement that has no upper bound, like `>= 1.0`
+///
+/// Returns false if any was found and `is_strict` is set
+pub(crate) fn check_dependency_requirements(
+    package: &cargo_metadata::Package,
+    is_strict: bool,
+) -> bool {
+    use cargo_metadata::semver::Op;
+    let label = if is_strict {
+        "error".red().bold()
+    } else {
+        "warning".yellow()
+    };
+    let mut is_valid = true;
+    for dependency in &package.dependencies {
+        let problem = if dependency.req.comparators.is_empty() {
+            // cargo removes dev-dependencies without a version from the published manifest
+            if dependency.kind == cargo_metadata::DependencyKind::Development
+                && dependency.path.is_some()
+            {
+                continue;
+            }
+            if dependency.path.is_some() {
+                "does not specify a version requirement".to_owned()
+            } else {
+                "uses the wildcard version requirement `*`".to_owned()
+            }
+        } else if dependency
+            .req
+            .comparators
+            .iter()
+            .all(|c| matches!(c.op, Op::Greater | Op::GreaterEq))
+        {
+            format!(
+                "uses the version requirement `{}` without an upper bound, \
+                 which includes future incompatible versions",
+                dependency.req
+            )
+        } else {
+            continue;
+        };
+        eprintln!(
+            "{label}: the dependency `{}` {problem}. Specify the oldest compatible version instead, \
+             e.g. `{} = \"1.2\"`",
+            dependency.name, dependency.name
+        );
+        is_valid = false;
+    }
+    is_valid || !is_strict
+}
+
+/// Warn about dependencies between publishable workspace members whose version
+/// requirement does not match the version of the member in the workspace
+///
+/// Users of the published crate would get a different version of the dependency
+/// than the one it was developed and verified with
+pub(crate) fn check_workspace_dependency_versions(metadata: &cargo_metadata::Metadata) {
+    let members = metadata
+        .workspace_packages()
+        .into_iter()
+        .filter(|p| is_publishable(p))
+        .collect::<Vec<_>>();
+    for package in &members {
+        for dependency in &package.dependencies {
+            // dev-dependencies are not relevant for users of the crate
+            if dependency.kind == cargo_metadata::DependencyKind::Development {
+                continue;
+            }
+            let Some(path) = &dependency.path else {
+                continue;
+            };
+            let Some(member) = members
+                .iter()
+                .find(|m| m.manifest_path.parent() == Some(path.as_path()))
+            else {
+                continue;
+            };
+            if !dependency.req.matches(&member.version) {
+                eprintln!(
+                    "{}: `{}` depends on `{}` with the version requirement `{}`, \
+                     which does not match the version {} of `{}` in the workspace",
+                    "warning".yellow(),
+                    package.name,
+                    dependency.name,
+                    dependency.req,
+                    member.version,
+                    member.name,
+                );
+            } else if let Some(minimum) = minimum_version(&dependency.req)
+                && minimum < member.version
+            {
+                eprintln!(
+                    "{}: `{}` depends on `{}` with the version requirement `{}`, \
+                     which allows versions older than the version {} in the workspace. \
+                     `{}` might not build with these versions",
+                    "warning".yellow(),
+                    package.name,
+                    dependency.name,
+                    dependency.req,
+                    member.version,
+                    package.name,
+                );
+            }
+        }
+    }
+}
+
+/// The smallest version allowed by the given requirement,
+/// `None` if the requirement has no lower bound
+fn minimum_version(
+    req: &cargo_metadata::semver::VersionReq,
+) -> Option<cargo_metadata::semver::Version> {
+    use cargo_metadata::semver::Op;
+    req.comparators
+        .iter()
+        .filter(|c| {
+            matches!(
+                c.op,
+                Op::Exact | Op::GreaterEq | Op::Tilde | Op::Caret | Op::Wildcard
+            )
+        })
+        .map(|c| {
+            let mut version = cargo_metadata::semver::Version::new(
+                c.major,
+                c.minor.unwrap_or(0),
+                c.patch.unwrap_or(0),
+            );
+            version.pre = c.pre.clone();
+            version
+        })
+        .max()
+}
+
+/// `publish = false` is represented as an empty list of registries
+pub(crate) fn is_publishable(package: &cargo_metadata::Package) -> bool {
+    package
+        .publish
+        .as_ref()
+        .is_none_or(|registries| !registries.is_empty())
+}
+
+/// Warn about `panic = "abort"` in the release profile of library crates
+pub(crate) fn check_panic_abort(package: &cargo_metadata::Package) {
+    let is_library = package
+        .targets
+        .iter()
+        .any(|t| t.is_lib() || t.is_rlib() || t.is_dylib());
+    if !is_library {
+        return;
+    }
+    let manifest = cargo_toml::Manifest::from_path(&package.manifest_path)
+        .expect("Failed to read `Cargo.toml`");
+    let panic = manifest
+        .profile
+        .release
+        .as_ref()
+        .and_then(|p| p.panic.as_deref());
+    if panic == Some("abort") {
+        eprintln!(
+            "{}: the library crate `{}` sets `panic = \"abort\"` in `[profile.release]`. \
+             Cargo ignores profiles of dependencies, so this does not apply to users of the crate \
+             and they cannot rely on it. If the crate requires aborting on panic document that instead. \
+             Pass `--allow-panic-abort` to silence this warning",
+            "warning".yellow(),
+            package.name
+        );
+    }
+}
+
+/// Warn about build scripts that emit `cargo:rerun-if-changed=.`
+///
+/// This makes cargo scan the whole package on every build and rerun the build script
+/// whenever any file changes, which slows down builds for all users of the crate
+pub(crate) fn check_build_script_rerun(package: &cargo_metadata::Package) {
+    let pattern =
+        regex::Regex::new(r#"cargo::?rerun-if-changed=\./?""#).expect("The regex is valid");
+    let package_root = package.manifest_path.parent().unwrap();
+    for target in package.targets.iter().filter(|t| t.is_custom_build()) {
+        let Ok(content) = std::fs::read_to_string(&target.src_path) else {
+            continue;
+        };
+        let path = target
+            .src_path
+            .strip_prefix(package_root)
+            .unwrap_or(&target.src_path);
+        for (line_number, line) in content.lines().enumerate() {
+            if line.trim_start().starts_with("//") || !pattern.is_match(line) {
+                continue;
+            }
+            eprintln!(
+                "{}: the build script emits `cargo:rerun-if-changed=.` in {path}:{}. \
+                 Cargo then reruns the build script whenever any file of the package changes, \
+                 which slows down builds for users of the crate. \
+                 List the files the build script actually reads instead",
+                "warning".yellow(),
+                line_number + 1
+            );
+        }
+    }
+}
+
+/// Warn about dependencies that are declared as optional and as required at the same time,
+/// about features that are named like a required dependency and about features
+/// that differ between `cargo metadata` and the `[features]` table of `Cargo.toml`
+pub(crate) fn check_feature_consistency(package: &cargo_metadata::Package) {
+    let dependency_name =
+        |d: &cargo_metadata::Dependency| d.rename.clone().unwrap_or_else(|| d.name.clone());
+    let normal_dependencies = package
+        .dependencies
+        .iter()
+        .filter(|d| d.kind == cargo_metadata::DependencyKind::Normal)
+        .collect::<Vec<_>>();
+    let optional = normal_dependencies
+        .iter()
+        .filter(|d| d.optional)
+        .map(|d| dependency_name(d))
+        .collect::<BTreeSet<_>>();
+    let required = normal_dependencies
+        .iter()
+        .filter(|d| !d.optional)
+        .map(|d| dependency_name(d))
+        .collect::<BTreeSet<_>>();
+
+    for dependency in optional.intersection(&required) {
+        eprintln!(
+            "{}: the dependency `{dependency}` is declared as optional and as required, \
+             e.g. in a target specific dependency table. `optional = true` has no effect \
+             wherever the required declaration applies",
+            "warning".yellow()
+        );
+    }
+    for feature in package.features.keys() {
+        if required.contains(feature) && !optional.contains(feature) {
+            eprintln!(
+                "{}: the feature `{feature}` has the same name as the required dependency `{feature}`, \
+                 the dependency is always enabled independent of the feature",
+                "warning".yellow()
+            );
+        }
+    }
+
+    let Some(declared) = std::fs::read_to_string(&package.manifest_path)
+        .ok()
+        .and_then(|content| content.parse::<toml::Table>().ok())
+        .map(|manifest| {
+            manifest
+                .get("features")
+                .and_then(|f| f.as_table())
+                .map(|features| features.keys().cloned().collect::<BTreeSet<_>>())
+                .unwrap_or_default()
+        })
+    else {
+        return;
+    };
+    for (feature, enables) in &package.features {
+        // cargo adds an implicit feature for each optional dependency not used via `dep:`
+        let is_implicit = optional.contains(feature) && *enables == [format!("dep:{feature}")];
+        if !declared.contains(feature) && !is_implicit {
+            eprintln!(
+                "{}: `cargo metadata` reports the feature `{feature}`, \
+                 which is not declared in the `[features]` table of `{}`",
+                "warning".yellow(),
+                package.manifest_path
+            );
+        }
+    }
+    for feature in declared.difference(&package.features.keys().cloned().collect()) {
+        eprintln!(
+            "{}: the feature `{feature}` is declared in the `[features]` table of `{}`, \
+             but `cargo metadata` does not report it",
+            "warning".yellow(),
+            package.manifest_path
+        );
+    }
+}
+
+/// Returns false if a dev-dependency enables features of a package that is also part of
+/// the regular dependency tree without these features
+///
+/// cargo unifies the features of both declarations for tests and examples, so these
+/// might only pass because of the dev-dependency while the published crate lacks the features.
+/// Expects the metadata to contain the resolved dependency graph
+pub(crate) fn check_dev_dependency_features(
+    metadata: &cargo_metadata::Metadata,
+    package: &cargo_metadata::Package,
+) -> bool {
+    let Some(resolve) = &metadata.resolve else {
+        return true;
+    };
+    let nodes = resolve
+        .nodes
+        .iter()
+        .map(|node| (&node.id, node))
+        .collect::<BTreeMap<_, _>>();
+    let packages = metadata
+        .packages
+        .iter()
+        .map(|package| (&package.id, package))
+        .collect::<BTreeMap<_, _>>();
+    let Some(root) = nodes.get(&package.id) else {
+        return true;
+    };
+    let is_dev_dependency = |kinds: &[cargo_metadata::DepKindInfo]| {
+        kinds
+            .iter()
+            .any(|k| k.kind == cargo_metadata::DependencyKind::Development)
+    };
+    let is_regular_dependency = |kinds: &[cargo_metadata::DepKindInfo]| {
+        kinds
+            .iter()
+            .any(|k| k.kind != cargo_metadata::DependencyKind::Development)
+    };
+
+    // everything the published crate depends on, without following any dev-dependency
+    let mut regular = BTreeSet::from([&package.id]);
+    let mut pending = vec![&package.id];
+    while let Some(id) = pending.pop() {
+        let Some(node) = nodes.get(id) else {
+            continue;
+        };
+        for dependency in &node.deps {
+            if is_regular_dependency(&dependency.dep_kinds) && regular.insert(&dependency.pkg) {
+                pending.push(&dependency.pkg);
+            }
+        }
+    }
+
+    let mut is_ok = true;
+    for dev_dependency in root
+        .deps
+        .iter()
+        .filter(|d| is_dev_dependency(&d.dep_kinds) && regular.contains(&d.pkg))
+    {
+        let Some(dependency_package) = packages.get(&dev_dependency.pkg) else {
+            continue;
+        };
+        let requested_features = |declaration: &cargo_metadata::Dependency| {
+            let is_default = declaration.uses_default_features
+                && dependency_package.features.contains_key("default");
+            declaration
+                .features
+                .iter()
+                .cloned()
+                .chain(is_default.then(|| "default".to_owned()))
+                .collect::<Vec<_>>()
+        };
+        let is_declaration = |declaration: &&cargo_metadata::Dependency| {
+            declaration.name == dependency_package.name.as_str()
+                && declaration.req.matches(&dependency_package.version)
+        };
+        let regular_features = regular
+            .iter()
+            .filter_map(|id| packages.get(id))
+            .flat_map(|p| &p.dependencies)
+            .filter(|d| d.kind != cargo_metadata::DependencyKind::Development)
+            .filter(is_declaration)
+            .flat_map(requested_features)
+            .collect::<BTreeSet<_>>();
+        let dev_only_features = package
+            .dependencies
+            .iter()
+            .filter(|d| d.kind == cargo_metadata::DependencyKind::Development)
+            .filter(is_declaration)
+            .flat_map(requested_features)
+            .filter(|feature| !regular_features.contains(feature))
+            .collect::<BTreeSet<_>>();
+        if dev_only_features.is_empty() {
+            continue;
+        }
+        is_ok = false;
+        eprintln!(
+            "{}: the dev-dependency `{}` ({}) enables the features {} that the regular dependency tree \
+             does not enable, so tests and examples build with features the published crate does not get",
+            "error".red().bold(),
+            dependency_package.name,
+            dependency_package.version,
+            dev_only_features
+                .iter()
+                .map(|f| format!("`{f}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    is_ok
+}
+
+/// Returns false if `package.repository` is missing or not a http(s) url
+pub(crate) fn check_repository(package: &cargo_metadata::Package) -> bool {
+    match package.repository.as_deref().map(str::trim) {
+        None | Some("") => {
+            eprintln!(
+                "{}: `{}` does not set `package.repository`, \
+                 users need it to find the source code, report issues or contribute",
+                "error".red().bold(),
+                package.name
+            );
+            false
+        }
+        Some(repository)
+            if !(repository.starts_with("https://") || repository.starts_with("http://")) =>
+        {
+            eprintln!(
+                "{}: `package.repository` of `{}` is set to `{repository}`, \
+                 which is not a url starting with `https://` or `http://`",
+                "error".red().bold(),
+                package.name
+            );
+            false
+        }
+        Some(_) => true,
+    }
+}
+
+/// Returns false if `[dependencies]` or `[build-dependencies]` contain a dependency
+/// that only sets `path`, which cargo rejects at publish time
+pub(crate) fn check_no_path_dependencies(package_root: &cargo_metadata::camino::Utf8Path) -> bool {
+    let manifest_path = package_root.join("Cargo.toml");
+    let manifest = std::fs::read_to_string(&manifest_path)
+        .expect("Failed to read `Cargo.toml`")
+        .parse::<toml::Table>()
+        .expect("`Cargo.toml` is valid toml");
+    let targets = manifest
+        .get("target")
+        .and_then(toml::Value::as_table)
+        .into_iter()
+        .flat_map(|targets| {
+            targets.iter().filter_map(|(target, table)| {
+                Some((format!("target.{target}."), table.as_table()?))
+            })
+        });
+    let mut is_valid = true;
+    for (prefix, table) in std::iter::once((String::new(), &manifest)).chain(targets) {
+        for section in ["dependencies", "build-dependencies"] {
+            let Some(dependencies) = table.get(section).and_then(toml::Value::as_table) else {
+                continue;
+            };
+            for (name, dependency) in dependencies {
+                let Some(dependency) = dependency.as_table() else {
+                    continue;
+                };
+                if dependency.contains_key("path") && !dependency.contains_key("version") {
+                    eprintln!(
+                        "{}: the dependency `{name}` in `[{prefix}{section}]` of `{manifest_path}` only sets `path`, \
+                         which cannot be published. Add a `version` key with the version of `{name}` \
+                         that is published to the registry",
+                        "error".red().bold()
+                    );
+                    is_valid = false;
+                }
+            }
+        }
+    }
+    is_valid
+}
+
+/// Keys docs.rs understands in `[package.metadata.docs.rs]`
+///
+/// See <https://docs.rs/about/metadata>
+const DOCS_RS_KEYS: &[&str] = &[
+    "features",
+    "all-features",
+    "no-default-features",
+    "default-target",
+    "targets",
+    "additional-targets",
+    "rustc-args",
+    "rustdoc-args",
+    "cargo-args",
+];
+
+/// Validate the `[package.metadata.docs.rs]` table
+///
+/// Returns false for problems that break the docs.rs build, all
+/// other problems are reported as warnings
+pub(crate) fn check_docs_rs_metadata(package: &cargo_metadata::Package) -> bool {
+    let Some(docs_rs) = package.metadata.get("docs").and_then(|d| d.get("rs")) else {
+        return true;
+    };
+    let Some(docs_rs) = docs_rs.as_object() else {
+        eprintln!(
+            "{}: `package.metadata.docs.rs` is not a table",
+            "error".red().bold()
+        );
+        return false;
+    };
+    let mut is_valid = true;
+
+    for key in docs_rs.keys() {
+        if !DOCS_RS_KEYS.contains(&key.as_str()) {
+            let suggestion = DOCS_RS_KEYS
+                .iter()
+                .find(|k| {
+                    k.replace('-', "_") == key.replace('-', "_") || k.trim_end_matches('s') == key
+                })
+                .map(|k| format!(", did you mean `{k}`?"))
+                .unwrap_or_default();
+            eprintln!(
+                "{}: unknown key `package.metadata.docs.rs.{key}`, docs.rs will ignore it{suggestion}",
+                "warning".yellow()
+            );
+        }
+    }
+
+    let features = docs_rs
+        .get("features")
+        .and_then(|f| f.as_array())
+        .map(|f| f.iter().filter_map(|f| f.as_str()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    for feature in &features {
+        if !feature_exists(package, feature) {
+            eprintln!(
+                "{}: `package.metadata.docs.rs.features` contains the unknown feature `{feature}`, \
+                 this breaks the docs.rs build",
+                "error".red().bold()
+            );
+            is_valid = false;
+        }
+    }
+    if !features.is_empty() && docs_rs.get("all-features").and_then(|a| a.as_bool()) == Some(true) {
+        eprintln!(
+            "{}: `package.metadata.docs.rs.features` has no effect as `all-features` is enabled",
+            "warning".yellow()
+        );
+    }
+
+    let targets = docs_rs
+        .get("default-target")
+        .and_then(|t| t.as_str())
+        .map(|t| ("default-target", t))
+        .into_iter()
+        .chain(
+            ["targets", "additional-targets"]
+                .into_iter()
+                .flat_map(|key| {
+                    docs_rs
+                        .get(key)
+                        .and_then(|t| t.as_array())
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|t| t.as_str())
+                        .map(move |t| (key, t))
+                }),
+        )
+        .collect::<Vec<_>>();
+    if !targets.is_empty()
+        && let Some(known_targets) = known_targets()
+    {
+        for (key, target) in targets {
+            if !known_targets.iter().any(|t| t == target) {
+                eprintln!(
+                    "{}: `package.metadata.docs.rs.{key}` contains the unknown target `{target}`",
+                    "warning".yellow()
+                );
+            }
+        }
+    }
+
+    is_valid
+}
+
+/// Returns true if `feature` can be enabled for the package via `--features`
+fn feature_exists(package: &cargo_metadata::Package, feature: &str) -> bool {
+    if let Some((dependency, _)) = feature.split_once('/') {
+        let dependency = dependency.trim_end_matches('?');
+        return package
+            .dependencies
+            .iter()
+            .any(|d| d.rename.as_deref().unwrap_or(&d.name) == dependency);
+    }
+    package.features.contains_key(feature)
+        // optional dependencies define an implicit feature
+        || package
+            .dependencies
+            .iter()
+            .any(|d| d.optional && d.rename.as_deref().unwrap_or(&d.name) == feature)
+}
+
+/// All targets rustc knows about, `None` if rustc could not be queried
+fn known_targets() -> Option<Vec<String>> {
+    let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = std::process::Command::new(rustc)
+        .args(["--print", "target-list"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|t| t.trim().to_owned())
+            .collect(),
+    )
+}
+
+/// Check that the changelog contains an entry for the version to publish
+///
+/// Any markdown heading that mentions the version matches, which covers
+/// `## [1.2.3] - 2025-01-01` (Keep a Changelog), `## 1.2.3 (2025-01-01)`
+/// (conventional changelog) and `# v1.2.3`
+pub(crate) fn check_changelog(
+    changelog: &std::path::Path,
+    package_version: &cargo_metadata::semver::Version,
+) -> bool {
+    let content = match std::fs::read_to_string(changelog) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!(
+                "{}: failed to read the changelog `{}`: {e}",
+                "error".red().bold(),
+                changelog.display()
+            );
+            return false;
+        }
+    };
+    let version = package_version.to_string();
+    let headings = content
+        .lines()
+        .filter(|line| line.trim_start().starts_with('#'))
+        .collect::<Vec<_>>();
+    if headings
+        .iter()
+        .any(|heading| contains_version(heading, &version))
+    {
+        return true;
+    }
+
+    eprintln!(
+        "{}: the changelog `{}` does not contain an entry for version {version}",
+        "error".red().bold(),
+        changelog.display()
+    );
+    let latest_entries = headings
+        .iter()
+        .filter(|heading| heading.chars().any(|c| c.is_ascii_digit()))
+        .take(3)
+        .collect::<Vec<_>>();
+    if !latest_entries.is_empty() {
+        eprintln!("The latest entries are:");
+        for entry in latest_entries {
+            eprintln!("  {}", entry.trim());
+        }
+    }
+    false
+}
+
+/// Returns true if `line` contains `version` not as part of a longer version number
+fn contains_version(line: &str, version: &str) -> bool {
+    line.match_indices(version).any(|(idx, _)| {
+        let before = line[..idx].chars().next_back();
+        let after = line[idx + version.len()..].chars().next();
+        !before.is_some_and(|c| c.is_ascii_digit() || c == '.')
+            && !after.is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+    })
+}
+
+/// Check that each configured group of platform specific files is part of the published crate
+///
+/// A group is represented if at least one published file matches one of its globs
+pub(crate) fn check_platform_files(
+    package_root: &cargo_metadata::camino::Utf8Path,
+    platform_files: &BTreeMap<String, Vec<String>>,
+    report: &VerificationReport,
+) -> bool {
+    let mut is_complete = true;
+    for (group, globs) in platform_files {
+        let mut builder = ignore::overrides::OverrideBuilder::new(package_root);
+        for glob in globs {
+            if let Err(e) = builder.add(glob) {
+                eprintln!(
+                    "{}: invalid glob `{glob}` in `platform_files.{group}`: {e}",
+                    "error".red().bold()
+                );
+                std::process::exit(1);
+            }
+        }
+        let matcher = builder
+            .build()
+            .expect("Building a matcher from valid globs never fails");
+        if !report
+            .files
+            .iter()
+            .any(|file| matcher.matched(&file.path, false).is_whitelist())
+        {
+            eprintln!(
+                "{}: the published crate does not contain any file of the platform group `{group}` ({})",
+                "error".red().bold(),
+                globs.join(", ")
+            );
+            is_complete = false;
+        }
+    }
+    is_complete
+}
+
+/// Print the location of every `debug_assert!`, `debug_assert_eq!` and `debug_assert_ne!`
+/// in the sources of the package
+///
+/// These are only informational, with `as_warning` they are reported as warnings
+pub(crate) fn report_debug_assertions(
+    package_root: &cargo_metadata::camino::Utf8Path,
+    as_warning: bool,
+) {
+    let pattern = regex::Regex::new(r"\bdebug_assert(_eq|_ne)?!").expect("The regex is valid");
+    let label = if as_warning {
+        "warning".yellow()
+    } else {
+        "note".cyan()
+    };
+    for entry in ignore::WalkBuilder::new(package_root)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "rs"))
+    {
+        let Ok(content) = std::fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        let path = entry
+            .path()
+            .strip_prefix(package_root)
+            .unwrap_or(entry.path());
+        for (line_number, line) in content.lines().enumerate() {
+            if line.trim_start().starts_with("//") {
+                continue;
+            }
+            if let Some(m) = pattern.find(line) {
+                eprintln!(
+                    "{label}: `{}` in {}:{}",
+                    m.as_str(),
+                    path.display(),
+                    line_number + 1
+                );
+            }
+        }
+    }
+}
+
+/// Warn about public items inside `#[cfg(test)]` modules and about public `#[cfg(test)]` items
+/// in the sources of the library
+///
+/// These become part of the public API whenever the crate is compiled with `--cfg test`
+pub(crate) fn report_test_exports(package: &cargo_metadata::Package) {
+    let Some(library) = package.targets.iter().find(|t| t.is_lib()) else {
+        return;
+    };
+    let Some(source_root) = library.src_path.parent() else {
+        return;
+    };
+    let package_root = package.manifest_path.parent().unwrap();
+    let cfg_test = regex::Regex::new(r"^#\[cfg\(test\)\]").expect("The regex is valid");
+    let module =
+        regex::Regex::new(r"^(pub(\([^)]*\))?\s+)?mod\s+\w+\s*\{").expect("The regex is valid");
+    // `pub(crate)` and similar restricted visibilities are not part of the public API
+    let public_item = regex::Regex::new(
+        r"^pub\s+(unsafe\s+)?(async\s+)?(const\s+)?(fn|struct|enum|union|trait|type|const|static|mod|use|macro)\b",
+    )
+    .expect("The regex is valid");
+    for entry in ignore::WalkBuilder::new(source_root)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "rs"))
+    {
+        let Ok(content) = std::fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        let path = entry
+            .path()
+            .strip_prefix(package_root)
+            .unwrap_or(entry.path());
+        let mut depth = 0_usize;
+        let mut is_after_cfg_test = false;
+        // the brace depth outside of the `#[cfg(test)]` module we are currently in
+        let mut test_module_depth = None;
+        for (line_number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.starts_with("//") {
+                continue;
+            }
+            if cfg_test.is_match(line) {
+                is_after_cfg_test = true;
+            } else if is_after_cfg_test && test_module_depth.is_none() && module.is_match(line) {
+                test_module_depth = Some(depth);
+                is_after_cfg_test = false;
+            } else if (is_after_cfg_test || test_module_depth.is_some())
+                && public_item.is_match(line)
+            {
+                eprintln!(
+                    "{}: the public item in {}:{} is only compiled for tests. \
+                     It becomes part of the public API of the crate whenever the crate is compiled \
+                     with `--cfg test`, consider using `pub(crate)` instead",
+                    "warning".yellow(),
+                    path.display(),
+                    line_number + 1
+                );
+                is_after_cfg_test = false;
+            } else if !line.starts_with("#[") && !line.is_empty() {
+                is_after_cfg_test = false;
+            }
+            let opened = line.matches('{').count();
+            let closed = line.matches('}').count();
+            depth = (depth + opened).saturating_sub(closed);
+            if test_module_depth.is_some_and(|module_depth| depth <= module_depth) {
+                test_module_depth = None;
+            }
+        }
+    }
+}
+
+/// Files that are usually packaged by accident
+const DEFAULT_BLOCKED_FILES: &[&str] = &[
+    "/target/**",
+    "**/.DS_Store",
+    "**/Thumbs.db",
+    "**/*.rs.bk",
+    "**/*.swp",
+];
+
+/// Report all files of the packaged crate that match the default blocklist
+/// or the additional `blocked_files` globs from the configuration
+///
+/// Returns false if any blocked file was found
+pub(crate) fn check_blocked_files(
+    package_root: &cargo_metadata::camino::Utf8Path,
+    crate_file: &[u8],
+    blocked_files: &[String],
+    is_strict: bool,
+) -> bool {
+    let mut builder = ignore::overrides::OverrideBuilder::new(package_root);
+    for glob in DEFAULT_BLOCKED_FILES
+        .iter()
+        .copied()
+        .chain(blocked_files.iter().map(String::as_str))
+    {
+        if let Err(e) = builder.add(glob) {
+            eprintln!(
+                "{}: invalid glob `{glob}` in `blocked_files`: {e}",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        }
+    }
+    let matcher = builder
+        .build()
+        .expect("Building a matcher from valid globs never fails");
+
+    let label = if is_strict {
+        "error".red().bold()
+    } else {
+        "warning".yellow()
+    };
+    let mut is_clean = true;
+    for path in packaged_files(crate_file) {
+        if matcher.matched(&path, false).is_whitelist() {
+            eprintln!(
+                "{label}: the packaged crate contains `{}`, which is usually not meant to be published. \
+                 Exclude it via `package.exclude` in `Cargo.toml`",
+                path.display()
+            );
+            is_clean = false;
+        }
+    }
+    is_clean
+}
+
+/// The paths of all files in the packaged crate, relative to the package root,
+/// sorted the same way as `cargo package --list` sorts them
+pub(crate) fn packaged_files(crate_file: &[u8]) -> Vec<std::path::PathBuf> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(crate_file));
+    archive
+        .entries()
+        .expect("Could not open the packaged `.crate` archive")
+        .map(|entry| {
+            let entry = entry.expect("Failed to get file entry from tar archive");
+            let path = entry.path().expect("Invalid path in the packaged crate");
+            // strip the `{name}-{version}` directory all files are packaged in
+            path.components().skip(1).collect::<std::path::PathBuf>()
+        })
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// The content of all files in the packaged crate except for the ones generated by cargo,
+/// keyed by their path relative to the package root
+fn packaged_contents(crate_file: &[u8]) -> BTreeMap<std::path::PathBuf, Vec<u8>> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(crate_file));
+    archive
+        .entries()
+        .expect("Could not open the packaged `.crate` archive")
+        .filter_map(|entry| {
+            let mut entry = entry.expect("Failed to get file entry from tar archive");
+            let path = entry.path().expect("Invalid path in the packaged crate");
+            let path = path.components().skip(1).collect::<std::path::PathBuf>();
+            if crate::CARGO_GENERATED_FILES
+                .iter()
+                .any(|generated| path == Path::new(generated))
+            {
+                return None;
+            }
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(&mut entry, &mut content)
+                .expect("Failed to read file from tar archive");
+            Some((path, content))
+        })
+        .collect()
+}
+
+/// Compare the packaged crate with the latest published version before it
+///
+/// Returns false if more than `threshold` percent of the packaged files were added,
+/// removed or changed, which often indicates an accidental reformatting or mass change
+pub(crate) fn check_change_magnitude(
+    registry: &registry::Registry,
+    package_name: &str,
+    package_version: &cargo_metadata::semver::Version,
+    crate_file: &[u8],
+    threshold: f64,
+    is_strict: bool,
+) -> bool {
+    let Some(previous_version) = registry
+        .published_versions(package_name)
+        .into_iter()
+        .filter(|v| v < package_version)
+        .max()
+    else {
+        // nothing to compare the first version of a crate with
+        return true;
+    };
+    let mut previous_crate = Vec::new();
+    std::io::Read::read_to_end(
+        &mut registry.download(package_name, &previous_version),
+        &mut previous_crate,
+    )
+    .expect("Failed to download the previous version of the crate");
+
+    let packaged = packaged_contents(crate_file);
+    let published = packaged_contents(&previous_crate);
+    let all_files = packaged
+        .keys()
+        .chain(published.keys())
+        .collect::<BTreeSet<_>>();
+    let changed_files = all_files
+        .iter()
+        .filter(|path| packaged.get(**path) != published.get(**path))
+        .collect::<Vec<_>>();
+    let percentage = 100.0 * changed_files.len() as f64 / all_files.len().max(1) as f64;
+    if percentage <= threshold {
+        return true;
+    }
+
+    let severity = if is_strict {
+        "error".red().bold()
+    } else {
+        "warning".yellow()
+    };
+    eprintln!(
+        "{severity}: {percentage:.1}% of the packaged files changed since the last published version ({}), \
+         which exceeds the threshold of {threshold}%:",
+        previous_version.to_string().bold()
+    );
+    for path in changed_files {
+        let change = match (published.contains_key(*path), packaged.contains_key(*path)) {
+            (false, _) => "added",
+            (_, false) => "removed",
+            _ => "modified",
+        };
+        eprintln!("  * {} ({change})", path.display());
+    }
+    false
+}
+
+/// Print whether files from `examples/`, `benches/`, `tests/` and `src/bin/`
+/// are part of the packaged crate
+///
+/// Returns false if this does not match the `expect_*` values of the configuration
+pub(crate) fn check_packaged_directories(
+    crate_file: &[u8],
+    config: &crate::config::Config,
+) -> bool {
+    let files = packaged_files(crate_file);
+    let directories = [
+        ("examples", "expect_examples", config.expect_examples),
+        ("benches", "expect_benches", config.expect_benches),
+        ("tests", "expect_tests", config.expect_tests),
+        ("src/bin", "expect_binaries", config.expect_binaries),
+    ];
+    let mut is_expected = true;
+    crate::print_status("Packaged directories:");
+    for (directory, key, expected) in directories {
+        let count = files.iter().filter(|f| f.starts_with(directory)).count();
+        let state = if count == 0 {
+            "not included".to_owned()
+        } else {
+            format!("included ({count} files)")
+        };
+        crate::print_status(format_args!("  * {directory}/: {state}"));
+        match expected {
+            Some(true) if count == 0 => {
+                eprintln!(
+                    "{}: `{directory}/` is not part of the packaged crate, but `{key} = true` is set. \
+                     Check `package.include` and `package.exclude` in `Cargo.toml`",
+                    "error".red().bold()
+                );
+                is_expected = false;
+            }
+            Some(false) if count != 0 => {
+                eprintln!(
+                    "{}: `{directory}/` is part of the packaged crate, but `{key} = false` is set. \
+                     Check `package.include` and `package.exclude` in `Cargo.toml`",
+                    "error".red().bold()
+                );
+                is_expected = false;
+            }
+            _ => {}
+        }
+    }
+    is_expected
+}
+
+/// Readme files cargo picks up if `package.readme` is not set
+const DEFAULT_READMES: &[&str] = &["README.md", "README.txt", "README"];
+
+/// Check that the readme of the package is part of the packaged crate
+///
+/// With `readme = false` no default readme file is expected in the crate,
+/// otherwise the readme resolved by cargo, if any, needs to be part of it.
+/// A readme outside of the package directory is packaged next to `Cargo.toml`.
+pub(crate) fn check_readme(
+    package_root: &cargo_metadata::camino::Utf8Path,
+    is_readme_disabled: bool,
+    readme: Option<&cargo_metadata::camino::Utf8Path>,
+    crate_file: &[u8],
+) -> bool {
+    let files = packaged_files(crate_file);
+    if is_readme_disabled {
+        let packaged_readmes = files
+            .iter()
+            .filter(|f| {
+                DEFAULT_READMES
+                    .iter()
+                    .any(|readme| f.as_path() == Path::new(readme))
+            })
+            .collect::<Vec<_>>();
+        for readme in &packaged_readmes {
+            eprintln!(
+                "{}: `package.readme` is set to `false`, but the packaged crate contains `{}`. \
+                 Exclude it via `package.exclude` in `Cargo.toml` or set `package.readme` to its path",
+                "error".red().bold(),
+                readme.display()
+            );
+        }
+        return packaged_readmes.is_empty();
+    }
+    let Some(readme) = readme else {
+        return true;
+    };
+    // `..` can't be resolved with `canonicalize` as the file does not need to exist
+    let mut resolved = std::path::PathBuf::new();
+    for component in package_root.as_std_path().join(readme).components() {
+        match component {
+            std::path::Component::ParentDir => {
+                resolved.pop();
+            }
+            std::path::Component::CurDir => {}
+            component => resolved.push(component),
+        }
+    }
+    let expected = match resolved.strip_prefix(package_root) {
+        Ok(relative) => relative.to_owned(),
+        Err(_) => resolved.file_name().map(Into::into).unwrap_or_default(),
+    };
+    if files.contains(&expected) {
+        return true;
+    }
+    eprintln!(
+        "{}: the readme `{readme}` is not part of the packaged crate, expected it at `{}`",
+        "error".red().bold(),
+        expected.display()
+    );
+    false
+}
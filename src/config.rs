@@ -0,0 +1,279 @@
+// A safer version of cargo publish
+//
+// Copyright (C) 2025 Georg Semmler
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see
+// <https://www.gnu.org/licenses/>.
+
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE_NAME: &str = "safe-publish.toml";
+/// How many `extends` are followed from a single configuration file, which also stops cycles
+const MAX_EXTENDS_DEPTH: usize = 5;
+
+/// The `cargo safe-publish` configuration
+///
+/// Command line flags always take precedence over values set here
+#[derive(Debug, Default, serde_derive::Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    /// The minimum `cargo-safe-publish` version required for this configuration
+    pub(crate) min_version: Option<String>,
+    /// A regex the message of the `HEAD` commit needs to match
+    pub(crate) require_commit_format: Option<String>,
+    /// Require `package.repository` to be set to a http(s) url
+    pub(crate) require_repository: Option<bool>,
+    /// The changelog used by `--changelog-check`, relative to the package directory
+    pub(crate) changelog: Option<PathBuf>,
+    /// Groups of platform specific files, each group needs to be
+    /// represented in the published crate by at least one file
+    /// matching one of its globs
+    pub(crate) platform_files: BTreeMap<String, Vec<String>>,
+    /// Globs of files that must not be part of the published crate,
+    /// in addition to the built-in list
+    pub(crate) blocked_files: Vec<String>,
+    /// Whether files from `examples/` are expected in the published crate
+    pub(crate) expect_examples: Option<bool>,
+    /// Whether files from `benches/` are expected in the published crate
+    pub(crate) expect_benches: Option<bool>,
+    /// Whether files from `tests/` are expected in the published crate
+    pub(crate) expect_tests: Option<bool>,
+    /// Whether files from `src/bin/` are expected in the published crate
+    pub(crate) expect_binaries: Option<bool>,
+    pub(crate) notifications: Notifications,
+    pub(crate) verification: Verification,
+}
+
+/// The `[verification]` table
+#[derive(Debug, Default, serde_derive::Deserialize)]
+#[serde(default)]
+pub(crate) struct Verification {
+    /// Additional files that are compared with a different local file,
+    /// in addition to `Cargo.toml.orig` which is always compared with `Cargo.toml`
+    pub(crate) remap: Vec<Remap>,
+}
+
+#[derive(Debug, serde_derive::Deserialize)]
+pub(crate) struct Remap {
+    /// The path of the file in the published crate, relative to the package root
+    pub(crate) from: PathBuf,
+    /// The path of the local file to compare with, relative to the package root
+    pub(crate) to: PathBuf,
+}
+
+/// The `[notifications]` table
+#[derive(Debug, Default, serde_derive::Deserialize)]
+#[serde(default)]
+pub(crate) struct Notifications {
+    /// Send a JSON POST request to this url after a successful publish
+    pub(crate) webhook_url: Option<String>,
+    pub(crate) webhook_format: Option<WebhookFormat>,
+    /// The message to send, `{crate}`, `{version}`, `{registry}`, `{url}` and `{release_notes}` are replaced
+    pub(crate) message: Option<String>,
+}
+
+/// The shape of the JSON payload sent to the webhook
+#[derive(Debug, Default, Clone, Copy, serde_derive::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WebhookFormat {
+    Slack,
+    Discord,
+    #[default]
+    Generic,
+}
+
+impl Config {
+    /// Load and merge all configuration files that apply to the given package
+    ///
+    /// Files are considered in the following order, earlier files take precedence:
+    ///
+    /// * `safe-publish.toml` in the package directory
+    /// * `.cargo/safe-publish.toml` in the root of the git repository
+    /// * `safe-publish.toml` in `$CARGO_HOME`
+    pub(crate) fn load(package_root: &Path) -> Self {
+        let mut paths = vec![package_root.join(CONFIG_FILE_NAME)];
+        if let Some(git_root) = crate::get_git_root(package_root) {
+            paths.push(git_root.join(".cargo").join(CONFIG_FILE_NAME));
+        }
+        if let Some(cargo_home) = crate::registry::cargo_home() {
+            paths.push(cargo_home.join(CONFIG_FILE_NAME));
+        }
+
+        let merged =
+            paths
+                .iter()
+                .filter_map(load_table)
+                .fold(toml::Table::new(), |mut merged, table| {
+                    merge_tables(&mut merged, table);
+                    merged
+                });
+        // before deserializing, as newer configurations might not be understood by this version
+        check_min_version(&merged);
+        merged.try_into().unwrap_or_else(|e| {
+            eprintln!(
+                "{}: invalid `cargo safe-publish` configuration: {e}",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        })
+    }
+}
+
+/// Exit if `min_version` requires a newer `cargo-safe-publish` version
+///
+/// A plain version like `0.3.1` is treated as `>=0.3.1`,
+/// other version requirements are used as they are
+fn check_min_version(config: &toml::Table) {
+    let Some(min_version) = config.get("min_version") else {
+        return;
+    };
+    let requirement: cargo_metadata::semver::VersionReq = min_version
+        .as_str()
+        .and_then(|min_version| {
+            let min_version = min_version.trim();
+            if min_version.starts_with(|c: char| c.is_ascii_digit()) {
+                format!(">={min_version}").parse().ok()
+            } else {
+                min_version.parse().ok()
+            }
+        })
+        .unwrap_or_else(|| {
+            eprintln!(
+                "{}: invalid `min_version` `{min_version}` in the `cargo safe-publish` configuration, \
+                 expected a version like \"0.3.0\"",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        });
+    let version = cargo_metadata::semver::Version::parse(crate::APP_VERSION)
+        .expect("The version of cargo-safe-publish is valid");
+    if !requirement.matches(&version) {
+        eprintln!(
+            "{}: the `cargo safe-publish` configuration requires version `{requirement}` of cargo-safe-publish, \
+             but this is version {version}. Upgrade via `cargo install --locked cargo-safe-publish`",
+            "error".red().bold()
+        );
+        std::process::exit(1);
+    }
+}
+
+fn load_table(path: &PathBuf) -> Option<toml::Table> {
+    let content = std::fs::read_to_string(path).ok()?;
+    Some(parse_table(path, &content, 0))
+}
+
+/// Parse a configuration file and merge the file it `extends` into it, depth-first
+///
+/// The path in `extends` is relative to the directory of the extending file
+fn parse_table(path: &Path, content: &str, depth: usize) -> toml::Table {
+    let mut table = content.parse::<toml::Table>().unwrap_or_else(|e| {
+        eprintln!(
+            "{}: failed to parse the configuration file `{}`: {e}",
+            "error".red().bold(),
+            path.display()
+        );
+        std::process::exit(1);
+    });
+    substitute_env_vars(&mut table, path);
+    let Some(extends) = table.remove("extends") else {
+        return table;
+    };
+    let Some(extends) = extends.as_str() else {
+        eprintln!(
+            "{}: `extends` in the configuration file `{}` needs to be a path",
+            "error".red().bold(),
+            path.display()
+        );
+        std::process::exit(1);
+    };
+    if depth >= MAX_EXTENDS_DEPTH {
+        eprintln!(
+            "{}: `extends` in the configuration file `{}` exceeds the maximum chain of {MAX_EXTENDS_DEPTH} \
+             extended files, check the `extends` keys for a cycle",
+            "error".red().bold(),
+            path.display()
+        );
+        std::process::exit(1);
+    }
+    let base_path = path.parent().unwrap_or(Path::new("")).join(extends);
+    let base_content = std::fs::read_to_string(&base_path).unwrap_or_else(|e| {
+        eprintln!(
+            "{}: failed to read the configuration file `{}` extended by `{}`: {e}",
+            "error".red().bold(),
+            base_path.display(),
+            path.display()
+        );
+        std::process::exit(1);
+    });
+    let base = parse_table(&base_path, &base_content, depth + 1);
+    merge_tables(&mut table, base);
+    table
+}
+
+/// Replace `${NAME}` in all string values with the value of the environment variable `NAME`
+///
+/// Substituted values are not expanded again
+fn substitute_env_vars(table: &mut toml::Table, path: &Path) {
+    fn substitute(value: &mut toml::Value, pattern: &regex::Regex, path: &Path) {
+        match value {
+            toml::Value::String(s) => {
+                let substituted = pattern.replace_all(s, |captures: &regex::Captures<'_>| {
+                    let name = &captures[1];
+                    std::env::var(name).unwrap_or_else(|_| {
+                        eprintln!(
+                            "{}: the environment variable `{name}` used in the configuration file `{}` is not set",
+                            "error".red().bold(),
+                            path.display()
+                        );
+                        std::process::exit(1);
+                    })
+                });
+                *s = substituted.into_owned();
+            }
+            toml::Value::Array(values) => {
+                for value in values {
+                    substitute(value, pattern, path);
+                }
+            }
+            toml::Value::Table(table) => {
+                for (_, value) in table.iter_mut() {
+                    substitute(value, pattern, path);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let pattern = regex::Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").expect("The regex is valid");
+    for (_, value) in table.iter_mut() {
+        substitute(value, &pattern, path);
+    }
+}
+
+/// Merge `lower` into `higher`, values already present in `higher` win
+fn merge_tables(higher: &mut toml::Table, lower: toml::Table) {
+    for (key, value) in lower {
+        match (higher.get_mut(&key), value) {
+            (Some(toml::Value::Table(higher)), toml::Value::Table(lower)) => {
+                merge_tables(higher, lower)
+            }
+            (Some(_), _) => {}
+            (None, value) => {
+                higher.insert(key, value);
+            }
+        }
+    }
+}
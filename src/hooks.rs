@@ -0,0 +1,128 @@
+// A safer version of cargo publish
+//
+// Copyright (C) 2025 Georg Semmler
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see
+// <https://www.gnu.org/licenses/>.
+
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const HOOKS_DIRECTORY: &str = ".cargo-safe-publish-hooks.d";
+
+/// When a hook runs
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Stage {
+    /// After all checks passed, before the crate is published
+    PrePublish,
+    /// After the crate was published successfully
+    PostPublish,
+}
+
+impl Stage {
+    fn directory_name(self) -> &'static str {
+        match self {
+            Stage::PrePublish => "pre-publish",
+            Stage::PostPublish => "post-publish",
+        }
+    }
+}
+
+/// The hook directory for the given stage
+///
+/// The directory in the package root wins over the one in the root of the git repository
+pub(crate) fn hook_directory(package_root: &Path, stage: Stage) -> Option<PathBuf> {
+    std::iter::once(package_root)
+        .chain(crate::get_git_root(package_root))
+        .map(|root| root.join(HOOKS_DIRECTORY).join(stage.directory_name()))
+        .find(|directory| directory.is_dir())
+}
+
+/// Run all executable files in the hook directory of the given stage in alphabetical order
+///
+/// Stops at the first hook that fails and returns its exit code
+pub(crate) fn run_hooks(
+    package_root: &Path,
+    stage: Stage,
+    package_name: &str,
+    package_version: &cargo_metadata::semver::Version,
+    registry: &str,
+) -> Result<(), i32> {
+    let Some(directory) = hook_directory(package_root, stage) else {
+        return Ok(());
+    };
+    let mut hooks = std::fs::read_dir(&directory)
+        .and_then(|entries| {
+            entries
+                .map(|e| Ok(e?.path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .unwrap_or_else(|e| {
+            eprintln!(
+                "{}: failed to read the hook directory `{}`: {e}",
+                "error".red().bold(),
+                directory.display()
+            );
+            std::process::exit(1);
+        });
+    hooks.retain(|hook| is_executable(hook));
+    hooks.sort();
+
+    for hook in hooks {
+        crate::print_status(format_args!(
+            "Run the {} hook `{}`",
+            stage.directory_name(),
+            hook.display()
+        ));
+        let status = Command::new(&hook)
+            .current_dir(package_root)
+            .env("SAFE_PUBLISH_PACKAGE", package_name)
+            .env("SAFE_PUBLISH_VERSION", package_version.to_string())
+            .env("SAFE_PUBLISH_REGISTRY", registry)
+            .status();
+        match status {
+            Err(e) => {
+                eprintln!(
+                    "{}: failed to run the hook `{}`: {e}",
+                    "error".red().bold(),
+                    hook.display()
+                );
+                return Err(1);
+            }
+            Ok(s) if !s.success() => {
+                eprintln!(
+                    "{}: the hook `{}` returned a non-zero exit code, check the output above for details",
+                    "error".red().bold(),
+                    hook.display()
+                );
+                return Err(s.code().unwrap_or(1));
+            }
+            Ok(_) => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
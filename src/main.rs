@@ -18,14 +18,31 @@
 
 use colored::Colorize;
 use flate2::read::GzDecoder;
+use report::{
+    CheckResult, FileReport, FileStatus, PublishReport, ToolVersions, VerificationReport,
+};
+use sha2::Digest;
 use std::collections::HashMap;
-use std::io::Read;
+use std::io::{IsTerminal, Read};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
+mod checks;
+mod config;
+mod hooks;
+mod notification;
+mod registry;
+mod report;
+
 const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 const CARGO_GENERATED_FILES: &[&str] = &[".cargo_vcs_info.json", "Cargo.toml", "Cargo.lock"];
 const REMAP_FILES: [(&str, &str); 1] = [("Cargo.toml.orig", "Cargo.toml")];
+const DEFAULT_BENCHMARK_RUNS: usize = 3;
+const DEFAULT_VERIFICATION_WAIT_SECONDS: u64 = 5;
+const DEFAULT_EXAMPLE_TIMEOUT_SECONDS: u64 = 60;
+/// Files larger than this are compared chunk by chunk and no diff is rendered for them
+const MAX_TEXT_DIFF_SIZE: u64 = 1024 * 1024;
+const COMPARE_CHUNK_SIZE: usize = 64 * 1024;
 
 fn manifest_path() -> Option<String> {
     let mut args = std::env::args().skip_while(|c| !c.starts_with("--manifest-path"));
@@ -39,8 +56,8 @@ fn manifest_path() -> Option<String> {
 }
 
 fn package_flag() -> Option<String> {
-    let mut args =
-        std::env::args().skip_while(|c| !(c.starts_with("--package") || c.starts_with("-p")));
+    let mut args = std::env::args()
+        .skip_while(|c| !(c == "--package" || c.starts_with("--package=") || c == "-p"));
     match args.next() {
         Some(n) if n.starts_with("--package=") => {
             n.strip_prefix("--package=").map(|t| t.to_owned())
@@ -50,294 +67,2695 @@ fn package_flag() -> Option<String> {
     }
 }
 
-fn verify_content_matches(
-    package_root: &cargo_metadata::camino::Utf8Path,
-    package_version: &cargo_metadata::semver::Version,
-    package_name: &str,
-    lock_file_content: Option<String>,
-) -> bool {
-    let body = ureq::get(format!(
-        "https://crates.io/api/v1/crates/{package_name}/{package_version}/download"
-    ))
-    .header("User-Agent", format!("cargo-safe-publish/{APP_VERSION}"))
-    .call()
-    .expect("Failed to fetch package")
-    .body_mut()
-    .read_to_vec()
-    .expect("Failed to fetch package");
-    let remapped_files = HashMap::from(REMAP_FILES);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Human,
+    GithubMarkdown,
+    Json,
+}
 
-    let zipped_archive = GzDecoder::new(std::io::Cursor::new(body));
-    let mut archive = tar::Archive::new(zipped_archive);
-    let mut everything_matched = true;
-    for entry in archive
-        .entries()
-        .expect("Could not open uploaded `.crate` archive")
-    {
-        let mut entry = entry.expect("Failed to get file entry from tar archive");
+/// How strict files are compared during the content verification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareMode {
+    /// Byte by byte
+    Exact,
+    /// Ignore a byte order mark, line endings and trailing whitespace,
+    /// compare manifests and lock files by their parsed content
+    Semantic,
+}
 
-        let path = entry.path().unwrap().into_owned();
-        let mut package_local_path = path
-            .strip_prefix(format!("{package_name}-{package_version}"))
-            .unwrap()
-            .to_path_buf();
+/// How files are compared and reported during the content verification,
+/// parsed once from the command line
+#[derive(Debug, Clone, Copy)]
+struct CompareOptions {
+    mode: CompareMode,
+    is_summary_only: bool,
+}
 
-        // we want to make sure that we compare `Cargo.toml.orig` to the local `Cargo.toml` as otherwise
-        // they don't match
-        if let Some(remap_file) = remapped_files.get(path.file_name().unwrap().to_str().unwrap()) {
-            package_local_path = package_local_path.parent().unwrap().join(*remap_file);
+impl CompareOptions {
+    fn from_args() -> Self {
+        Self {
+            mode: compare_mode(),
+            is_summary_only: is_summary_only(),
         }
+    }
+}
 
-        let local_path = package_root.join(package_local_path.display().to_string());
-        let relative_item_path = path.file_name().unwrap().to_str().unwrap();
-        if !CARGO_GENERATED_FILES.contains(&relative_item_path) {
-            if relative_item_path == "Cargo.lock" {
-                // Cargo.lock files are regenerated by cargo on publish
-                // so we manually need to compare them
-                if let Some(lock_content) = lock_file_content.clone() {
-                    let mut uploaded_content = String::new();
-                    entry
-                        .read_to_string(&mut uploaded_content)
-                        .expect("Failed to read file from tar archive");
-                    compare_content(
-                        &mut everything_matched,
-                        &package_local_path,
-                        uploaded_content,
-                        lock_content,
-                    );
-                }
-            } else if local_path.exists() {
-                let mut uploaded_content = String::new();
-                entry
-                    .read_to_string(&mut uploaded_content)
-                    .expect("Failed to read file from tar archive");
-                let local_content =
-                    std::fs::read_to_string(local_path).expect("Could not read local file");
-                compare_content(
-                    &mut everything_matched,
-                    &package_local_path,
-                    uploaded_content,
-                    local_content,
-                );
-            } else {
-                eprintln!(
-                    "{}: the file `{path}` does not exist in `{package_root}`",
-                    "error".red().bold(),
-                    path = package_local_path.display().to_string().bold(),
-                );
-                everything_matched = false;
-            }
+fn compare_mode() -> CompareMode {
+    match flag_value("--compare-mode").as_deref() {
+        None | Some("exact") => CompareMode::Exact,
+        Some("semantic") => CompareMode::Semantic,
+        Some(mode) => {
+            eprintln!(
+                "{}: unknown compare mode `{mode}`, expected one of `exact` or `semantic`",
+                "error".red().bold()
+            );
+            std::process::exit(1);
         }
     }
-    everything_matched
 }
 
-fn compare_content(
-    everything_matched: &mut bool,
-    package_local_path: &Path,
-    uploaded_content: String,
-    local_content: String,
-) {
-    if local_content != uploaded_content {
-        let diff = similar_asserts::SimpleDiff::from_str(
-            &local_content,
-            &uploaded_content,
-            "Local version",
-            "Uploaded version",
+fn output_format() -> OutputFormat {
+    match flag_value("--output-format") {
+        None => OutputFormat::Human,
+        Some(format) if format == "human" => OutputFormat::Human,
+        Some(format) if format == "github-markdown" => OutputFormat::GithubMarkdown,
+        Some(format) if format == "json" => OutputFormat::Json,
+        Some(format) => {
+            eprintln!(
+                "{}: unknown output format `{format}`, expected one of `human`, `github-markdown` or `json`",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Flags without a value that are handled by `cargo safe-publish` itself
+/// and therefore are never forwarded to `cargo publish`
+const SAFE_PUBLISH_SWITCHES: &[&str] = &[
+    "--no-wait",
+    "--ignore-rustflags",
+    "--keep-going",
+    "--fail-fast",
+    "--no-fail-fast",
+    "--missing-as-warning",
+    "--strict",
+    "--deny-cargo-warning",
+    "--require-full-clone",
+    "--diff-exit-zero",
+    "--treat-diff-as-warning",
+    "--constant-time-compare",
+    "--report-unverified-files",
+    "--inspect",
+    "--clean-verify",
+    "--verify-msrv",
+    "--miri-check",
+    "--verify-examples",
+    "--allow-nonmonotonic",
+    "--workspace-version-sync",
+    "--require-no-path-deps",
+    "--deny-wildcard-deps",
+    "--detect-unpublished-changes",
+    "--strip-dev-deps",
+    "--refresh-index",
+    "--check-feature-powerset",
+    "--check-name-collision",
+    "--allow-panic-abort",
+    "--require-repository",
+    "--changelog-check",
+    "--benchmark",
+    "--archive-list",
+    "--print-file-list",
+    "--warn-debug-assertions",
+    "--compare-committed",
+    "--print-manifest",
+    "--check-manifest-dependencies",
+    "--no-network",
+    "--no-publish",
+    "--check-test-exports",
+    "--summary-only",
+];
+
+/// Flags with a value that are handled by `cargo safe-publish` itself
+/// and therefore are never forwarded to `cargo publish`
+///
+/// The value can be passed as `--flag value` or as `--flag=value`
+const SAFE_PUBLISH_VALUE_FLAGS: &[&str] = &[
+    "--output-format",
+    "--wait",
+    "--report-file",
+    "--attestation",
+    "--require-commit-format",
+    "--user-agent",
+    "--header",
+    "--verify-target",
+    "--benchmark-runs",
+    "--example-timeout",
+    "--skip-example",
+    "--against-git-ref",
+    "--index-path",
+    "--crate-file",
+    "--allow-cargo-warning",
+    "--release",
+    "--diff-context",
+    "--since-tag",
+    "--git-backend",
+    "--only",
+    "--only-files",
+    "--download-mirror",
+    "--compare-mode",
+    "--binary-diff-tool",
+    "--change-threshold",
+    "--target-dir",
+    "--feature-powerset-depth",
+    "--publish-args-file",
+    "--release-notes",
+    "--package-filter",
+];
+
+/// Cargo flags that `cargo safe-publish` reads as well,
+/// so they cannot be passed via `--publish-args-file`
+const INSPECTED_CARGO_FLAGS: &[&str] = &[
+    "--allow-dirty",
+    "--color",
+    "--dry-run",
+    "--help",
+    "-h",
+    "--manifest-path",
+    "--no-verify",
+    "--package",
+    "-p",
+    "--quiet",
+    "-q",
+    "--registry",
+    "--token",
+    "--verbose",
+    "-v",
+    "-vv",
+    "--workspace",
+];
+
+/// The arguments that are forwarded to `cargo publish`,
+/// that is all arguments except for the flags handled by `cargo safe-publish`
+fn cargo_arguments() -> Vec<String> {
+    let mut args = arguments();
+    let mut forwarded = Vec::new();
+    while let Some(arg) = args.next() {
+        if SAFE_PUBLISH_SWITCHES.contains(&arg.as_str()) {
+            continue;
+        }
+        if SAFE_PUBLISH_VALUE_FLAGS.contains(&arg.as_str()) {
+            // skip the value of `--flag value`
+            args.next();
+            continue;
+        }
+        if let Some((flag, _)) = arg.split_once('=')
+            && SAFE_PUBLISH_VALUE_FLAGS.contains(&flag)
+        {
+            continue;
+        }
+        forwarded.push(arg);
+    }
+    forwarded.extend(publish_args_file_arguments());
+    forwarded
+}
+
+/// The arguments listed in the file passed via `--publish-args-file`
+///
+/// Exits if the file cannot be read or contains flags `cargo safe-publish` reads itself
+fn publish_args_file_arguments() -> Vec<String> {
+    let Some(file) = flag_value("--publish-args-file") else {
+        return Vec::new();
+    };
+    let content = std::fs::read_to_string(&file).unwrap_or_else(|e| {
+        eprintln!(
+            "{}: failed to read the publish arguments file `{file}`: {e}",
+            "error".red().bold()
         );
+        std::process::exit(1);
+    });
+    let args = split_arguments(&content).unwrap_or_else(|e| {
         eprintln!(
-            "{}: found differences in `{}`:",
-            "error".red().bold(),
-            package_local_path.display().to_string().bold()
+            "{}: failed to parse the publish arguments file `{file}`: {e}",
+            "error".red().bold()
         );
-        eprintln!("{diff}");
-        *everything_matched = false;
+        std::process::exit(1);
+    });
+    for arg in &args {
+        let flag = arg.split_once('=').map_or(arg.as_str(), |(flag, _)| flag);
+        if SAFE_PUBLISH_SWITCHES.contains(&flag)
+            || SAFE_PUBLISH_VALUE_FLAGS.contains(&flag)
+            || INSPECTED_CARGO_FLAGS.contains(&flag)
+        {
+            eprintln!(
+                "{}: `{flag}` in the publish arguments file `{file}` is read by cargo safe-publish, \
+                 pass it on the command line instead",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        }
+    }
+    args
+}
+
+/// Split the content of an arguments file the way a POSIX shell would split a command line
+///
+/// Supports single and double quotes, backslash escapes and `#` comments.
+/// Arguments can be separated by any whitespace, including newlines
+fn split_arguments(content: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(current.take()),
+            '#' if current.is_none() => {
+                chars.by_ref().find(|c| *c == '\n');
+            }
+            '\'' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err("unterminated single quote".to_owned()),
+                    }
+                }
+            }
+            '"' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => arg.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                            None => return Err("unterminated double quote".to_owned()),
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err("unterminated double quote".to_owned()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // a backslash at the end of a line continues the argument on the next line
+                Some('\n') => {}
+                Some(c) => current.get_or_insert_with(String::new).push(c),
+                None => return Err("trailing backslash".to_owned()),
+            },
+            c => current.get_or_insert_with(String::new).push(c),
+        }
     }
+    args.extend(current);
+    Ok(args)
 }
 
-fn run_publish() {
-    let mut publish_command = Command::new("cargo");
+fn report_file() -> Option<PathBuf> {
+    flag_value("--report-file").map(PathBuf::from)
+}
 
-    publish_command
-        .arg("publish")
-        .arg("--no-verify")
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit());
+/// The content of the Markdown file passed via `--release-notes`
+fn release_notes() -> Option<String> {
+    let file = flag_value("--release-notes")?;
+    let release_notes = std::fs::read_to_string(&file).unwrap_or_else(|e| {
+        eprintln!(
+            "{}: failed to read the release notes `{file}`: {e}",
+            "error".red().bold()
+        );
+        std::process::exit(1);
+    });
+    Some(release_notes)
+}
 
-    // append all the other flags
-    for arg in std::env::args()
-        .skip(1)
-        .filter(|c| c != "--no-verify" && c != "safe-publish")
-    {
-        publish_command.arg(arg);
+/// A reader that calculates the sha256 checksum of everything read through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: sha2::Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
     }
+}
 
-    println!("Run cargo publish with the following command: `{publish_command:?}`");
-    let publish_status = publish_command.status();
-    match publish_status {
-        Err(e) => {
-            eprintln!("{}: publish run failed: {e}", "error".red().bold());
+/// How long to wait after the upload before downloading the published crate again
+///
+/// Returns `None` if the content verification should be skipped entirely
+fn verification_wait() -> Option<std::time::Duration> {
+    if std::env::args().any(|c| c == "--no-wait") {
+        return None;
+    }
+    let seconds = match flag_value("--wait") {
+        None => DEFAULT_VERIFICATION_WAIT_SECONDS,
+        Some(seconds) => seconds.parse().unwrap_or_else(|_| {
+            eprintln!(
+                "{}: invalid value `{seconds}` for `--wait`, expected a number of seconds",
+                "error".red().bold()
+            );
             std::process::exit(1);
-        }
-        Ok(s) if !s.success() => {
+        }),
+    };
+    Some(std::time::Duration::from_secs(seconds))
+}
+
+/// The percentage of changed files passed via `--change-threshold`
+fn change_threshold() -> Option<f64> {
+    let percentage = flag_value("--change-threshold")?;
+    match percentage.trim_end_matches('%').parse::<f64>() {
+        Ok(percentage) if (0.0..=100.0).contains(&percentage) => Some(percentage),
+        _ => {
             eprintln!(
-                "{}: publish run returned a non-zero exist code, check the output above for details",
+                "{}: invalid value `{percentage}` for `--change-threshold`, expected a percentage between 0 and 100",
                 "error".red().bold()
             );
-            std::process::exit(s.code().unwrap_or(1));
+            std::process::exit(1);
         }
-        Ok(_) => {}
     }
 }
 
-fn run_verification_build(
-    target_directory: &Path,
-    package_name: &str,
-    package_version: &cargo_metadata::semver::Version,
-) -> String {
-    let mut dry_run_command = Command::new("cargo");
+/// The maximum number of features combined by `--check-feature-powerset`
+fn feature_powerset_depth() -> Option<usize> {
+    let depth = flag_value("--feature-powerset-depth")?;
+    match depth.parse() {
+        Ok(depth) if depth > 0 => Some(depth),
+        _ => {
+            eprintln!(
+                "{}: invalid value `{depth}` for `--feature-powerset-depth`, expected a positive number",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        }
+    }
+}
 
-    dry_run_command
-        .arg("publish")
-        .arg("--dry-run")
-        .stderr(Stdio::inherit())
-        .stdout(Stdio::inherit());
+/// How long each example may run with `--verify-examples`
+fn example_timeout() -> std::time::Duration {
+    let seconds = match flag_value("--example-timeout") {
+        None => DEFAULT_EXAMPLE_TIMEOUT_SECONDS,
+        Some(seconds) => seconds.parse().unwrap_or_else(|_| {
+            eprintln!(
+                "{}: invalid value `{seconds}` for `--example-timeout`, expected a number of seconds",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        }),
+    };
+    std::time::Duration::from_secs(seconds)
+}
 
-    // append all the other flags
-    for arg in std::env::args()
-        .skip(1)
-        .filter(|c| c != "--dry-run" && c != "safe-publish")
-    {
-        dry_run_command.arg(arg);
+fn write_report(report: &VerificationReport, output_format: OutputFormat) {
+    if std::env::args().any(|c| c == "--report-unverified-files") {
+        let unverified = report
+            .files
+            .iter()
+            .filter_map(|f| Some((f, f.unverified_reason()?)))
+            .collect::<Vec<_>>();
+        print_status("");
+        print_status(format_args!(
+            "{} of {} files were not compared with the local file at the same path:",
+            unverified.len(),
+            report.files.len()
+        ));
+        for (file, reason) in unverified {
+            print_status(format_args!(
+                "  * {}: {reason}",
+                file.archive_path.display()
+            ));
+        }
     }
-    println!("Run verification build with the following command: `{dry_run_command:?}`");
-    let dry_run_status = dry_run_command.status();
-    match dry_run_status {
-        Err(e) => {
-            eprintln!("{}: dry run failed: {e}", "error".red().bold());
-            std::process::exit(1);
+    let is_quiet = std::env::args().any(|c| c == "--quiet" || c == "-q");
+    let markdown = report.to_github_markdown();
+    match output_format {
+        OutputFormat::Human => {
+            println!();
+            println!("Verified files:");
+            print!("{}", report.to_summary_table(is_quiet));
         }
-        Ok(s) if !s.success() => {
+        OutputFormat::GithubMarkdown => {
+            println!();
+            print!("{markdown}");
+        }
+        OutputFormat::Json => println!("{}", report.to_summary_json(is_quiet)),
+    }
+    // Github actions render everything written to this file on the job summary page
+    if let Some(step_summary) = std::env::var_os("GITHUB_STEP_SUMMARY") {
+        let res = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&step_summary)
+            .and_then(|mut file| std::io::Write::write_all(&mut file, markdown.as_bytes()));
+        if let Err(e) = res {
             eprintln!(
-                "{}: dry run returned a non-zero exist code, check the output above for details",
-                "error".red().bold()
+                "{}: failed to write the step summary to `{}`: {e}",
+                "warning".yellow(),
+                step_summary.display()
             );
-            std::process::exit(s.code().unwrap_or(1));
         }
-        Ok(_) => {}
     }
+}
 
-    // cargo should remove these files on it's own on the new call to `cargo publish` with the same version
-    // but we better make sure that they are gone instead of relying on that behavior
-    let unpacked_target_package = target_directory
-        .join("package")
-        .join(format!("{package_name}-{package_version}"));
-    let target_package = target_directory
-        .join("package")
-        .join(format!("{package_name}-{package_version}.crate"));
+/// Configure colored output based on `--color` in the same way cargo does
+///
+/// `--color` is a regular cargo flag and therefore also forwarded to cargo
+fn configure_color() {
+    let color = flag_value("--color").or_else(|| std::env::var("CARGO_TERM_COLOR").ok());
+    colored::control::set_override(is_color_enabled(color.as_deref()));
+}
 
-    let lock_file = unpacked_target_package.join("Cargo.lock");
-    let lock_file_content =
-        std::fs::read_to_string(lock_file).expect("Failed to read new lock file");
+/// Whether the given `--color` value enables colored output
+fn is_color_enabled(color: Option<&str>) -> bool {
+    match color {
+        None | Some("auto") => {
+            if std::env::var_os("CLICOLOR_FORCE").is_some_and(|v| v != "0") {
+                true
+            } else if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+                false
+            } else {
+                // all our diagnostics are written to stderr
+                std::io::stderr().is_terminal()
+            }
+        }
+        Some("always") => true,
+        Some("never") => false,
+        Some(color) => {
+            eprintln!(
+                "error: invalid value `{color}` for `--color`, expected one of `auto`, `always` or `never`"
+            );
+            std::process::exit(1);
+        }
+    }
+}
 
-    std::fs::remove_dir_all(unpacked_target_package).expect(
-        "Failed to remove unpacked package from the target directory during the verification build",
-    );
-    std::fs::remove_file(target_package).expect(
-        "Failed to remove the packed crate from the target directory during the verification build",
-    );
+/// Parse `--user-agent=<agent>` and the repeatable `--header=<name: value>` flags
+fn request_options() -> registry::RequestOptions {
+    let user_agent = flag_value("--user-agent");
+    let headers = flag_values("--header")
+        .into_iter()
+        .map(|header| match header.split_once(':') {
+            Some((name, value)) if !name.trim().is_empty() => {
+                (name.trim().to_owned(), value.trim().to_owned())
+            }
+            _ => {
+                eprintln!(
+                    "{}: invalid header `{header}`, expected `Name: Value`",
+                    "error".red().bold()
+                );
+                std::process::exit(1);
+            }
+        })
+        .collect();
+    registry::RequestOptions {
+        user_agent,
+        headers,
+        is_refresh_index: std::env::args().any(|c| c == "--refresh-index"),
+    }
+}
 
-    lock_file_content
+/// Returns the `cargo safe-publish` subcommand if any
+///
+/// Subcommands are always the first argument after the (optional) `safe-publish`
+/// argument cargo passes to us
+fn subcommand() -> Option<String> {
+    arguments()
+        .next()
+        .filter(|c| c == "verify" || c == "status" || c == "registries")
 }
 
-fn get_git_root(package_root: &Path) -> Option<&Path> {
-    let mut check_dir = Some(package_root);
-    loop {
-        let local_check_dir = check_dir?;
-        if local_check_dir.join(".git").exists() {
-            return Some(local_check_dir);
-        } else {
-            check_dir = local_check_dir.parent();
+/// The command line arguments without the binary name
+///
+/// Cargo passes the subcommand name as first argument when invoked as `cargo safe-publish`,
+/// which is stripped here so that running the binary directly behaves the same.
+/// A following `publish` argument is stripped as well, so that `cargo safe-publish`
+/// can be used as drop-in replacement for `cargo publish` via a shell alias
+fn arguments() -> impl Iterator<Item = String> {
+    let args = std::env::args().collect::<Vec<_>>();
+    strip_invocation(&args).to_vec().into_iter()
+}
+
+/// Strip the binary name, a leading `safe-publish` and a following `publish`
+/// from the given command line
+fn strip_invocation(args: &[String]) -> &[String] {
+    let mut args = args.get(1..).unwrap_or_default();
+    for name in ["safe-publish", "publish"] {
+        if let Some(rest) = args.strip_prefix(&[name.to_owned()]) {
+            args = rest;
         }
     }
+    args
 }
 
-fn check_git_is_dirty(package_root: &cargo_metadata::camino::Utf8Path) {
-    if let Some(git_root) = get_git_root(package_root.as_std_path()) {
-        let manifest = cargo_toml::Manifest::from_path(package_root.join("Cargo.toml"))
-            .expect("Failed to read `Cargo.toml`");
-        let include = manifest
-            .package
-            .as_ref()
-            .map(|p| p.include())
-            .and_then(|i| (!i.is_empty()).then_some(i));
-        let exclude = manifest
-            .package
-            .as_ref()
-            .map(|p| p.exclude())
-            .and_then(|e| (!e.is_empty()).then_some(e));
+/// Returns the value of a flag passed as either `--flag value` or `--flag=value`
+fn flag_value(flag: &str) -> Option<String> {
+    flag_values(flag).into_iter().next()
+}
 
-        if include.is_some() && exclude.is_some() {
-            eprintln!(
-                "{}: both `package.include` and `package.exclude` are set. Cargo will ignore `package.exclude` in this case",
-                "warning".yellow()
-            );
+/// Returns the values of all occurrences of a flag passed as either `--flag value` or `--flag=value`
+fn flag_values(flag: &str) -> Vec<String> {
+    let with_value = format!("{flag}=");
+    let mut args = std::env::args();
+    let mut values = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            values.extend(args.next());
+        } else if let Some(value) = arg.strip_prefix(&with_value) {
+            values.push(value.to_owned());
         }
+    }
+    values
+}
 
-        let include = include.map(|p| {
-            p.iter()
-                .fold(
-                    ignore::gitignore::GitignoreBuilder::new(package_root),
-                    |mut builder, i| {
-                        builder.add_line(None, i).unwrap();
-                        builder
-                    },
-                )
-                .build()
-                .unwrap()
-        });
-        let exclude = exclude.map(|p| {
-            p.iter()
-                .fold(
-                    ignore::gitignore::GitignoreBuilder::new(package_root),
-                    |mut builder, i| {
-                        builder.add_line(None, i).unwrap();
-                        builder
-                    },
-                )
-                .build()
-                .unwrap()
-        });
+/// Where the local version of the published files is read from
+enum LocalSource<'repo> {
+    WorkingTree,
+    GitTree {
+        git_ref: String,
+        tree: gix::Tree<'repo>,
+        /// The path of the package relative to the git root
+        package_prefix: PathBuf,
+    },
+}
 
-        let (patterns, sub_dir) = if package_root == git_root {
-            (
-                Box::new(std::iter::empty()) as Box<dyn Iterator<Item = _>>,
-                None,
-            )
-        } else {
-            let package_path_in_git = package_root
-                .as_std_path()
-                .strip_prefix(git_root)
-                .expect("The package_root path is a child path or equivalent to the git root path");
-            let only_in_subdir = gix::diff::object::bstr::BString::new(
-                package_path_in_git.as_os_str().as_encoded_bytes().to_vec(),
-            );
-            (
-                Box::new(std::iter::once(only_in_subdir.clone())) as Box<dyn Iterator<Item = _>>,
-                Some(only_in_subdir),
-            )
-        };
+impl LocalSource<'_> {
+    /// Open the file at `path` relative to the package root
+    ///
+    /// Returns the content of the file and its size, or `None` if the file does not exist
+    fn open(
+        &self,
+        package_root: &cargo_metadata::camino::Utf8Path,
+        path: &Path,
+    ) -> Option<(Box<dyn Read + '_>, u64)> {
+        match self {
+            LocalSource::WorkingTree => {
+                let local_path = package_root.as_std_path().join(path);
+                if !local_path.exists() {
+                    return None;
+                }
+                let local_file =
+                    std::fs::File::open(&local_path).expect("Could not read local file");
+                let local_size = local_file
+                    .metadata()
+                    .expect("Could not read local file")
+                    .len();
+                Some((Box::new(local_file), local_size))
+            }
+            LocalSource::GitTree {
+                tree,
+                package_prefix,
+                ..
+            } => {
+                let entry = tree
+                    .lookup_entry_by_path(package_prefix.join(path))
+                    .expect("Failed to read git tree")?;
+                if !entry.mode().is_blob() {
+                    return None;
+                }
+                let object = entry.object().expect("Failed to read git object");
+                let local_size = object.data.len() as u64;
+                Some((
+                    Box::new(std::io::Cursor::new(object.detach().data)),
+                    local_size,
+                ))
+            }
+        }
+    }
 
-        let repo = gix::open(git_root).expect("Could not open git repo");
-        let status = repo
-                .status(gix::progress::Discard)
-                .expect("Failed to get repo state")
-                .untracked_files(gix::status::UntrackedFiles::Files)
-                .into_iter(patterns)
-                .expect("Failed to get repo state")
-                .filter_map(|i| {
-                    let item = match i {
-                        Ok(i) => i,
+    fn describe(&self, package_root: &cargo_metadata::camino::Utf8Path) -> String {
+        match self {
+            LocalSource::WorkingTree => format!("`{package_root}`"),
+            LocalSource::GitTree { git_ref, .. } => format!("the git ref `{git_ref}`"),
+        }
+    }
+}
+
+/// Compare the content of the given crate archive with the local files
+///
+/// Returns the exit code as error if the archive itself is malformed
+fn verify_content_matches(
+    crate_file: impl Read,
+    local_source: &LocalSource<'_>,
+    package: &cargo_metadata::Package,
+    lock_file_content: Option<String>,
+    remaps: &[config::Remap],
+) -> Result<VerificationReport, i32> {
+    let package_root = package.manifest_path.parent().unwrap();
+    let package_version = &package.version;
+    let package_name = package.name.as_str();
+    let license_file = package.license_file.as_deref().map(|l| l.as_std_path());
+    let remapped_files = HashMap::from(REMAP_FILES);
+    let is_missing_as_warning = std::env::args().any(|c| c == "--missing-as-warning");
+    let only_files = only_files();
+    let compare_options = CompareOptions::from_args();
+
+    let zipped_archive = GzDecoder::new(HashingReader {
+        inner: crate_file,
+        hasher: sha2::Sha256::new(),
+    });
+    let mut archive = tar::Archive::new(zipped_archive);
+    let mut files = Vec::new();
+    let mut manifest = None;
+    for entry in archive
+        .entries()
+        .expect("Could not open uploaded `.crate` archive")
+    {
+        let mut entry = entry.expect("Failed to get file entry from tar archive");
+
+        let path = entry.path().unwrap().into_owned();
+        let uploaded_size = entry.size();
+        let top_level_directory = format!("{package_name}-{package_version}");
+        let archive_path = match strip_top_level_directory(&path, &top_level_directory) {
+            Some(archive_path) => archive_path.to_path_buf(),
+            None => {
+                eprintln!(
+                    "{}: the crate archive contains the unexpected entry `{}`, \
+                     all entries are expected to be inside the `{top_level_directory}` directory",
+                    "error".red().bold(),
+                    path.display()
+                );
+                return Err(1);
+            }
+        };
+        let mut package_local_path = archive_path.clone();
+        if archive_path == Path::new("Cargo.toml") {
+            let mut content = String::new();
+            entry
+                .read_to_string(&mut content)
+                .expect("Failed to read the packaged `Cargo.toml`");
+            manifest = Some(content);
+        }
+
+        // paths are kept as `Path` as file names might not be valid UTF-8 on unix
+        let file_name = path.file_name().unwrap_or_default();
+        // we want to make sure that we compare `Cargo.toml.orig` to the local `Cargo.toml` as otherwise
+        // they don't match
+        if let Some(remap_file) = file_name.to_str().and_then(|f| remapped_files.get(f)) {
+            package_local_path = package_local_path.parent().unwrap().join(*remap_file);
+        }
+        if let Some(remap) = remaps.iter().find(|r| r.from == archive_path) {
+            package_local_path = remap.to.clone();
+        }
+        if let Some(only_files) = &only_files
+            && !only_files
+                .iter()
+                .any(|f| *f == archive_path || *f == package_local_path)
+        {
+            files.push(FileReport {
+                path: package_local_path,
+                archive_path,
+                status: FileStatus::Excluded,
+                local_size: None,
+                uploaded_size,
+            });
+            continue;
+        }
+
+        let (status, local_size) = if !CARGO_GENERATED_FILES.iter().any(|f| file_name == *f) {
+            if file_name == "Cargo.lock" {
+                // Cargo.lock files are regenerated by cargo on publish
+                // so we manually need to compare them
+                if let Some(lock_content) = &lock_file_content {
+                    let local_size = lock_content.len() as u64;
+                    (
+                        compare_content(
+                            &package_local_path,
+                            &mut entry,
+                            uploaded_size,
+                            lock_content.as_bytes(),
+                            local_size,
+                            false,
+                            compare_options,
+                        ),
+                        Some(local_size),
+                    )
+                } else {
+                    (FileStatus::Generated, None)
+                }
+            } else if let Some((local_file, local_size)) =
+                local_source.open(package_root, &package_local_path)
+            {
+                // a `license-file` outside of the package is packaged at the package root
+                let is_license_file = license_file.is_some_and(|license_file| {
+                    license_file == package_local_path
+                        || license_file
+                            .file_name()
+                            .is_some_and(|name| Path::new(name) == archive_path)
+                });
+                (
+                    compare_content(
+                        &package_local_path,
+                        &mut entry,
+                        uploaded_size,
+                        local_file,
+                        local_size,
+                        is_license_file,
+                        compare_options,
+                    ),
+                    Some(local_size),
+                )
+            } else if is_missing_as_warning {
+                eprintln!(
+                    "{}: the file `{path}` does not exist in {source}",
+                    "warning".yellow(),
+                    path = package_local_path.display().to_string().bold(),
+                    source = local_source.describe(package_root),
+                );
+                (FileStatus::MissingIgnored, None)
+            } else {
+                eprintln!(
+                    "{}: the file `{path}` does not exist in {source}",
+                    "error".red().bold(),
+                    path = package_local_path.display().to_string().bold(),
+                    source = local_source.describe(package_root),
+                );
+                (FileStatus::Missing, None)
+            }
+        } else if archive_path == Path::new("Cargo.toml")
+            && let Some(manifest) = &manifest
+            && !checks::check_manifest_edition(package, manifest)
+        {
+            (FileStatus::Differs, None)
+        } else {
+            (FileStatus::Generated, None)
+        };
+        files.push(FileReport {
+            path: package_local_path,
+            archive_path,
+            status,
+            local_size,
+            uploaded_size,
+        });
+    }
+    if let Some(only_files) = &only_files {
+        for path in only_files {
+            if !files.iter().any(|f| {
+                f.status != FileStatus::Excluded && (f.archive_path == *path || f.path == *path)
+            }) {
+                eprintln!(
+                    "{}: the file `{}` is not part of the crate",
+                    "warning".yellow(),
+                    path.display().to_string().bold()
+                );
+            }
+        }
+    }
+    // the tar archive might end before the compressed stream ends,
+    // so make sure to hash everything that was uploaded
+    let mut body = archive.into_inner().into_inner();
+    std::io::copy(&mut body, &mut std::io::sink()).expect("Failed to fetch package");
+    let checksum = body
+        .hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    Ok(VerificationReport {
+        package_name: package_name.to_owned(),
+        package_version: package_version.clone(),
+        checksum,
+        files,
+        manifest,
+    })
+}
+
+/// The path of an archive entry relative to the `{name}-{version}` directory
+///
+/// cargo packages all files into this single directory, anything else points
+/// to a malformed or tampered archive, for which `None` is returned
+fn strip_top_level_directory<'a>(path: &'a Path, top_level_directory: &str) -> Option<&'a Path> {
+    path.strip_prefix(top_level_directory)
+        .ok()
+        .filter(|archive_path| !archive_path.as_os_str().is_empty())
+}
+
+/// The files passed via `--only` and listed in the files passed via `--only-files`
+///
+/// Returns `None` if neither flag is passed, which means all files are verified
+fn only_files() -> Option<Vec<PathBuf>> {
+    let mut only_files = flag_values("--only")
+        .into_iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    let lists = flag_values("--only-files");
+    if only_files.is_empty() && lists.is_empty() {
+        return None;
+    }
+    for list in lists {
+        let content = std::fs::read_to_string(&list).unwrap_or_else(|e| {
+            eprintln!(
+                "{}: failed to read the file list `{list}`: {e}",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        });
+        // one path per line, empty lines and comments are ignored
+        only_files.extend(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(PathBuf::from),
+        );
+    }
+    Some(only_files)
+}
+
+/// Replace all CRLF line endings by LF
+fn normalize_line_endings(content: &[u8]) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(content.len());
+    let mut bytes = content.iter().copied().peekable();
+    while let Some(byte) = bytes.next() {
+        if byte == b'\r' && bytes.peek() == Some(&b'\n') {
+            continue;
+        }
+        normalized.push(byte);
+    }
+    normalized
+}
+
+/// Compare two files while ignoring differences `--compare-mode semantic` allows
+fn semantically_equal(path: &Path, local: &[u8], uploaded: &[u8]) -> bool {
+    let (Ok(local), Ok(uploaded)) = (std::str::from_utf8(local), std::str::from_utf8(uploaded))
+    else {
+        return false;
+    };
+    let strip_bom = |content: &'_ str| {
+        content
+            .strip_prefix('\u{feff}')
+            .unwrap_or(content)
+            .to_owned()
+    };
+    let (local, uploaded) = (strip_bom(local), strip_bom(uploaded));
+    let is_toml = path
+        .extension()
+        .is_some_and(|extension| extension == "toml" || extension == "lock");
+    if is_toml
+        && let (Ok(local), Ok(uploaded)) = (
+            local.parse::<toml::Table>(),
+            uploaded.parse::<toml::Table>(),
+        )
+    {
+        return local == uploaded;
+    }
+    let normalize = |content: &str| {
+        content
+            .lines()
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim_end()
+            .to_owned()
+    };
+    normalize(&local) == normalize(&uploaded)
+}
+
+fn compare_content(
+    package_local_path: &Path,
+    mut uploaded: impl Read,
+    uploaded_size: u64,
+    mut local: impl Read,
+    local_size: u64,
+    is_license_file: bool,
+    options: CompareOptions,
+) -> FileStatus {
+    if uploaded_size <= MAX_TEXT_DIFF_SIZE && local_size <= MAX_TEXT_DIFF_SIZE {
+        let mut uploaded_content = Vec::new();
+        uploaded
+            .read_to_end(&mut uploaded_content)
+            .expect("Failed to read file from tar archive");
+        let mut local_content = Vec::new();
+        local
+            .read_to_end(&mut local_content)
+            .expect("Could not read local file");
+        if local_content == uploaded_content {
+            return FileStatus::Matches;
+        }
+        // license texts are often copied with CRLF line endings from upstream
+        if is_license_file
+            && normalize_line_endings(&local_content) == normalize_line_endings(&uploaded_content)
+        {
+            print_status(format_args!(
+                "{}: the license file `{}` only matches after normalizing line endings",
+                "note".cyan(),
+                package_local_path.display()
+            ));
+            return FileStatus::Matches;
+        }
+        if options.mode == CompareMode::Semantic
+            && semantically_equal(package_local_path, &local_content, &uploaded_content)
+        {
+            print_status(format_args!(
+                "{}: `{}` only matches due to `--compare-mode semantic`",
+                "note".cyan(),
+                package_local_path.display()
+            ));
+            return FileStatus::Matches;
+        }
+        let is_text = std::str::from_utf8(&local_content).is_ok()
+            && std::str::from_utf8(&uploaded_content).is_ok();
+        if options.is_summary_only {
+            eprintln!(
+                "{}: found differences in `{}`",
+                "error".red().bold(),
+                package_local_path.display().to_string().bold()
+            );
+            return if is_text {
+                FileStatus::Differs
+            } else {
+                FileStatus::BinaryDiffers
+            };
+        }
+        eprintln!(
+            "{}: found differences in `{}`:",
+            "error".red().bold(),
+            package_local_path.display().to_string().bold()
+        );
+        if let (Ok(local_content), Ok(uploaded_content)) = (
+            std::str::from_utf8(&local_content),
+            std::str::from_utf8(&uploaded_content),
+        ) {
+            eprint!("{}", render_diff(local_content, uploaded_content));
+        } else {
+            report_binary_difference(package_local_path, &local_content, &uploaded_content);
+            return FileStatus::BinaryDiffers;
+        }
+        return FileStatus::Differs;
+    }
+
+    // large files are compared chunk by chunk without rendering a diff
+    let mut uploaded_chunk = vec![0; COMPARE_CHUNK_SIZE];
+    let mut local_chunk = vec![0; COMPARE_CHUNK_SIZE];
+    let mut uploaded_len = read_chunk(&mut uploaded, &mut uploaded_chunk)
+        .expect("Failed to read file from tar archive");
+    let mut local_len =
+        read_chunk(&mut local, &mut local_chunk).expect("Could not read local file");
+    // like git we consider a file binary if its first chunk contains a NUL byte
+    let is_binary =
+        uploaded_chunk[..uploaded_len].contains(&0) || local_chunk[..local_len].contains(&0);
+    let contents_match = uploaded_size == local_size
+        && loop {
+            if uploaded_chunk[..uploaded_len] != local_chunk[..local_len] {
+                break false;
+            }
+            if uploaded_len == 0 {
+                break true;
+            }
+            uploaded_len = read_chunk(&mut uploaded, &mut uploaded_chunk)
+                .expect("Failed to read file from tar archive");
+            local_len =
+                read_chunk(&mut local, &mut local_chunk).expect("Could not read local file");
+        };
+    if contents_match {
+        return FileStatus::Matches;
+    }
+    if options.is_summary_only {
+        eprintln!(
+            "{}: found differences in `{}`",
+            "error".red().bold(),
+            package_local_path.display().to_string().bold()
+        );
+    } else {
+        eprintln!(
+            "{}: found differences in `{}` (local size: {local_size} bytes, uploaded size: {uploaded_size} bytes). \
+             The file is too large to display a diff",
+            "error".red().bold(),
+            package_local_path.display().to_string().bold()
+        );
+    }
+    if is_binary {
+        FileStatus::BinaryDiffers
+    } else {
+        FileStatus::Differs
+    }
+}
+
+/// Whether only the paths of differing files should be reported, without any diffs
+fn is_summary_only() -> bool {
+    std::env::args().any(|c| c == "--summary-only")
+}
+
+/// Print the checksums of both versions of a differing binary file
+/// and run the `--binary-diff-tool`, if any
+fn report_binary_difference(package_local_path: &Path, local: &[u8], uploaded: &[u8]) {
+    let checksum = |content: &[u8]| {
+        sha2::Sha256::digest(content)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>()
+    };
+    eprintln!("Binary content differs:");
+    eprintln!(
+        "  {} sha256: {} ({} bytes)",
+        "Local version:   ".red(),
+        checksum(local),
+        local.len()
+    );
+    eprintln!(
+        "  {} sha256: {} ({} bytes, {:+})",
+        "Uploaded version:".green(),
+        checksum(uploaded),
+        uploaded.len(),
+        uploaded.len() as i64 - local.len() as i64
+    );
+
+    let Some(tool) = flag_value("--binary-diff-tool") else {
+        return;
+    };
+    let file_name = package_local_path.file_name().unwrap_or_default();
+    let temp_dir = std::env::temp_dir().join(format!(
+        "cargo-safe-publish-binary-diff-{}",
+        std::process::id()
+    ));
+    let local_path = temp_dir.join("local").join(file_name);
+    let uploaded_path = temp_dir.join("uploaded").join(file_name);
+    let written = [(&local_path, local), (&uploaded_path, uploaded)]
+        .into_iter()
+        .try_for_each(|(path, content)| {
+            std::fs::create_dir_all(path.parent().expect("The path has a parent"))?;
+            std::fs::write(path, content)
+        });
+    if let Err(e) = written {
+        eprintln!(
+            "{}: failed to write the files for `--binary-diff-tool`: {e}",
+            "warning".yellow()
+        );
+    } else {
+        // run via the shell, so that the tool can be a command with arguments
+        let mut tool_command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C").arg(format!(
+                "{tool} \"{}\" \"{}\"",
+                local_path.display(),
+                uploaded_path.display()
+            ));
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command
+                .arg("-c")
+                .arg(format!("{tool} \"$@\""))
+                .arg("sh")
+                .arg(&local_path)
+                .arg(&uploaded_path);
+            command
+        };
+        if let Err(e) = tool_command.status() {
+            eprintln!(
+                "{}: failed to run the binary diff tool `{tool}`: {e}",
+                "warning".yellow()
+            );
+        }
+    }
+    let _ = std::fs::remove_dir_all(&temp_dir);
+}
+
+/// Render a unified diff between the local and the uploaded content
+///
+/// The diff is colored via `colored`, so that it follows the
+/// same color settings as all other output
+fn render_diff(local_content: &str, uploaded_content: &str) -> String {
+    use std::fmt::Write;
+
+    let diff = similar::TextDiff::from_lines(local_content, uploaded_content);
+    let mut out = String::new();
+    writeln!(out, "{}", "--- Local version".red()).unwrap();
+    writeln!(out, "{}", "+++ Uploaded version".green()).unwrap();
+    let mut unified_diff = diff.unified_diff();
+    if let Some(context) = flag_value("--diff-context") {
+        let context = context.parse().unwrap_or_else(|_| {
+            eprintln!(
+                "{}: invalid value `{context}` for `--diff-context`, expected a number of lines",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        });
+        unified_diff.context_radius(context);
+    }
+    for hunk in unified_diff.iter_hunks() {
+        writeln!(out, "{}", hunk.header().to_string().cyan()).unwrap();
+        for change in hunk.iter_changes() {
+            let line = change.to_string_lossy();
+            let line = line.trim_end_matches(['\n', '\r']);
+            match change.tag() {
+                similar::ChangeTag::Delete => writeln!(out, "{}", format!("-{line}").red()),
+                similar::ChangeTag::Insert => writeln!(out, "{}", format!("+{line}").green()),
+                similar::ChangeTag::Equal => writeln!(out, " {line}"),
+            }
+            .unwrap();
+            if change.missing_newline() {
+                writeln!(out, "\\ No newline at end of file").unwrap();
+            }
+        }
+    }
+    out
+}
+
+/// Fills the given buffer as far as possible, returns the number of bytes read
+///
+/// Only returns less bytes than the buffer size if the end of the reader is reached
+fn read_chunk(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// The cargo binary to use, respecting the `CARGO` environment variable
+/// that cargo sets for subcommands
+fn cargo_command() -> Command {
+    let mut command = Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    if let Some(target_dir) = target_dir_flag() {
+        command.env("CARGO_TARGET_DIR", target_dir);
+    }
+    if is_no_network() {
+        command.env("CARGO_NET_OFFLINE", "true");
+    }
+    command
+}
+
+/// A cargo command for a specific rustup toolchain
+///
+/// This always goes through the rustup proxy on `PATH`, as `CARGO` usually
+/// points to the binary of a specific toolchain which does not understand `+toolchain`
+fn toolchain_cargo_command(toolchain: &str) -> Command {
+    let mut command = Command::new("cargo");
+    command.arg(format!("+{toolchain}"));
+    if let Some(target_dir) = target_dir_flag() {
+        command.env("CARGO_TARGET_DIR", target_dir);
+    }
+    if is_no_network() {
+        command.env("CARGO_NET_OFFLINE", "true");
+    }
+    command
+}
+
+/// The absolute path passed via `--target-dir`
+///
+/// It is passed on to all cargo invocations via `CARGO_TARGET_DIR`,
+/// so that the isolated target directory of `--clean-verify` can still override it
+fn target_dir_flag() -> Option<PathBuf> {
+    let target_dir = flag_value("--target-dir")?;
+    Some(std::path::absolute(&target_dir).unwrap_or_else(|_| PathBuf::from(target_dir)))
+}
+
+/// Exit with a helpful message if cargo is not available at all
+fn check_cargo_available() {
+    let status = cargo_command()
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    if let Err(e) = status
+        && e.kind() == std::io::ErrorKind::NotFound
+    {
+        match std::env::var_os("CARGO") {
+            Some(cargo) => eprintln!(
+                "{}: could not find the cargo binary `{}` set via the `CARGO` environment variable",
+                "error".red().bold(),
+                Path::new(&cargo).display()
+            ),
+            None => eprintln!(
+                "{}: could not find `cargo` on PATH; is the Rust toolchain installed?",
+                "error".red().bold()
+            ),
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Query the versions of cargo and rustc that are used for the verification build and the publish
+fn tool_versions() -> ToolVersions {
+    let cargo = cargo_command()
+        .args(["--version", "--verbose"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| {
+            // the first line contains the version, the following lines
+            // the commit hash, the host triple and the versions of the linked libraries
+            String::from_utf8_lossy(&o.stdout)
+                .lines()
+                .next()
+                .map(|l| l.trim().to_owned())
+        });
+    let rustc = Command::new(std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into()))
+        .arg("--version")
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned());
+    ToolVersions { cargo, rustc }
+}
+
+/// The host target triple as reported by `cargo --version --verbose`
+fn host_triple() -> Option<String> {
+    let output = cargo_command()
+        .args(["--version", "--verbose"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|l| l.strip_prefix("host:"))
+        .map(|host| host.trim().to_owned())
+}
+
+/// The metadata including the resolved dependency graph of the given package
+/// for the host platform
+///
+/// Uses the existing lock file as is, but creates one if there is none yet
+fn resolved_metadata(
+    package: &cargo_metadata::Package,
+    workspace_root: &cargo_metadata::camino::Utf8Path,
+) -> Result<cargo_metadata::Metadata, i32> {
+    let mut metadata_command = cargo_metadata::MetadataCommand::new();
+    metadata_command.manifest_path(&package.manifest_path);
+    let mut other_options = Vec::new();
+    if workspace_root.join("Cargo.lock").exists() {
+        other_options.push("--locked".to_owned());
+    }
+    if is_no_network() {
+        other_options.push("--offline".to_owned());
+    }
+    if let Some(host) = host_triple() {
+        other_options.extend_from_slice(&["--filter-platform".to_owned(), host]);
+    }
+    metadata_command.other_options(other_options);
+    metadata_command.exec().map_err(|e| {
+        eprintln!(
+            "{}: failed to resolve the dependency graph of `{}`: {e}",
+            "error".red().bold(),
+            package.name
+        );
+        1
+    })
+}
+
+fn is_verbose() -> bool {
+    std::env::args().any(|c| c == "--verbose" || c == "-v" || c == "-vv")
+}
+
+/// Whether all steps that need network access should be skipped
+///
+/// This also runs all cargo commands in offline mode
+fn is_no_network() -> bool {
+    std::env::args().any(|c| c == "--no-network")
+}
+
+/// Print the checks and steps that are skipped due to `--no-network`
+fn report_no_network_skips(skipped: &[(&str, bool)]) {
+    if !skipped.iter().any(|(_, is_skipped)| *is_skipped) {
+        return;
+    }
+    eprintln!(
+        "{}: `--no-network` skips the following steps:",
+        "note".cyan()
+    );
+    for (step, _) in skipped.iter().filter(|(_, is_skipped)| *is_skipped) {
+        eprintln!("  * {step}");
+    }
+}
+
+/// Whether stdout is reserved for the output of `--print-file-list` or `--output-format json`
+fn is_stdout_reserved() -> bool {
+    std::env::args().any(|c| c == "--print-file-list") || output_format() == OutputFormat::Json
+}
+
+/// Print a progress message, to stderr if stdout is reserved for machine readable output
+fn print_status(message: impl std::fmt::Display) {
+    if is_stdout_reserved() {
+        eprintln!("{message}");
+    } else {
+        println!("{message}");
+    }
+}
+
+/// Print the command that is about to run
+///
+/// In verbose mode this also prints the environment variables that influence cargo
+fn print_command(description: &str, command: &Command) {
+    print_status(format_args!(
+        "Run {description} with the following command: `{command:?}`"
+    ));
+    if is_verbose() {
+        for (key, value) in std::env::vars_os() {
+            let key = key.to_string_lossy();
+            if (key.starts_with("CARGO") || key.starts_with("RUST")) && !key.contains("TOKEN") {
+                print_status(format_args!("    {key}={}", value.to_string_lossy()));
+            }
+        }
+    }
+}
+
+/// Prints the time spent in each step of the publish process in verbose mode
+struct Timer {
+    last: std::time::Instant,
+    enabled: bool,
+}
+
+impl Timer {
+    fn new(enabled: bool) -> Self {
+        Self {
+            last: std::time::Instant::now(),
+            enabled,
+        }
+    }
+
+    /// Print the time elapsed since the last call
+    fn lap(&mut self, label: &str) {
+        let now = std::time::Instant::now();
+        if self.enabled {
+            print_status(format_args!(
+                "{}: {label} took {:.2?}",
+                "timing".cyan(),
+                now.duration_since(self.last)
+            ));
+        }
+        self.last = now;
+    }
+}
+
+/// Bump the version via `cargo release <level> --no-publish` before running the publish workflow
+///
+/// `cargo release` reads its own configuration (`release.toml` or `[package.metadata.release]`),
+/// so the tag format and the changelog updates configured there apply
+fn run_release(level: &str) -> Result<(), i32> {
+    let is_installed = cargo_command()
+        .args(["release", "--version"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
+    if !is_installed {
+        eprintln!(
+            "{}: `--release` requires `cargo-release`, install it via `cargo install cargo-release`",
+            "error".red().bold()
+        );
+        return Err(1);
+    }
+
+    let mut release_command = cargo_command();
+    release_command
+        .arg("release")
+        .arg(level)
+        .arg("--no-publish")
+        .arg("--execute")
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+    if let Some(package) = package_flag() {
+        release_command.arg("--package").arg(package);
+    }
+    if let Some(manifest_path) = manifest_path() {
+        release_command.arg("--manifest-path").arg(manifest_path);
+    }
+    print_command("version bump", &release_command);
+    match release_command.status() {
+        Ok(s) if s.success() => Ok(()),
+        Ok(s) => {
+            eprintln!(
+                "{}: `cargo release` returned a non-zero exit code, check the output above for details",
+                "error".red().bold()
+            );
+            Err(s.code().unwrap_or(1))
+        }
+        Err(e) => {
+            eprintln!(
+                "{}: failed to run `cargo release`: {e}",
+                "error".red().bold()
+            );
+            Err(1)
+        }
+    }
+}
+
+/// The pattern passed via `--package-filter`
+fn package_filter() -> Option<regex::Regex> {
+    let pattern = flag_value("--package-filter")?;
+    match regex::Regex::new(&pattern) {
+        Ok(filter) => Some(filter),
+        Err(e) => {
+            eprintln!(
+                "{}: invalid pattern `{pattern}` for `--package-filter`: {e}",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Run `cargo safe-publish` once for each publishable workspace member matching the filter
+///
+/// Members are processed in dependency order, so that dependencies are published first.
+/// Stops at the first package that fails
+fn run_filtered_packages(metadata: &cargo_metadata::Metadata, filter: &regex::Regex) {
+    let mut remaining = metadata
+        .workspace_packages()
+        .into_iter()
+        .filter(|p| checks::is_publishable(p) && filter.is_match(p.name.as_str()))
+        .collect::<Vec<_>>();
+    if remaining.is_empty() {
+        eprintln!(
+            "{}: no publishable workspace member matches `--package-filter={filter}`",
+            "error".red().bold()
+        );
+        std::process::exit(1);
+    }
+    let mut ordered = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        // dev-dependencies are not required to be published first
+        let next = remaining
+            .iter()
+            .position(|package| {
+                !package.dependencies.iter().any(|d| {
+                    d.kind != cargo_metadata::DependencyKind::Development
+                        && remaining
+                            .iter()
+                            .any(|other| other.id != package.id && other.name.as_str() == d.name)
+                })
+            })
+            .unwrap_or(0);
+        ordered.push(remaining.remove(next));
+    }
+    print_status(format_args!(
+        "Packages matching `{filter}`: {}",
+        ordered
+            .iter()
+            .map(|p| format!("`{}`", p.name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+
+    let mut args = std::env::args().skip(1);
+    let mut forwarded = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--package-filter" {
+            args.next();
+        } else if !arg.starts_with("--package-filter=") {
+            forwarded.push(arg);
+        }
+    }
+    let current_exe = std::env::current_exe().expect("Failed to find the current executable");
+    for package in ordered {
+        let mut command = Command::new(&current_exe);
+        command
+            .args(&forwarded)
+            .arg("--package")
+            .arg(package.name.as_str());
+        print_status("");
+        print_command(
+            &format!("cargo safe-publish for `{}`", package.name),
+            &command,
+        );
+        match command.status() {
+            Ok(s) if s.success() => {}
+            Ok(s) => {
+                eprintln!(
+                    "{}: cargo safe-publish failed for `{}`, not processing the remaining packages",
+                    "error".red().bold(),
+                    package.name
+                );
+                std::process::exit(s.code().unwrap_or(1));
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}: failed to run cargo safe-publish for `{}`: {e}",
+                    "error".red().bold(),
+                    package.name
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn run_publish(package_name: &str, registry: &registry::Registry) {
+    let mut publish_command = cargo_command();
+
+    publish_command
+        .arg("publish")
+        .arg("--no-verify")
+        .stdout(Stdio::inherit());
+
+    // append all the other flags
+    for arg in cargo_arguments().into_iter().filter(|c| c != "--no-verify") {
+        publish_command.arg(arg);
+    }
+
+    print_command("cargo publish", &publish_command);
+    // `cargo publish` has no structured output for registry errors,
+    // so we look for the known error messages in its stderr output
+    let publish_status = run_capturing_stderr(&mut publish_command);
+    match publish_status {
+        Err(e) => {
+            eprintln!("{}: publish run failed: {e}", "error".red().bold());
+            std::process::exit(1);
+        }
+        Ok((s, stderr)) if !s.success() => {
+            if !report_name_conflict(package_name, registry, &stderr) {
+                eprintln!(
+                    "{}: publish run returned a non-zero exist code, check the output above for details",
+                    "error".red().bold()
+                );
+            }
+            std::process::exit(s.code().unwrap_or(1));
+        }
+        Ok(_) => {}
+    }
+}
+
+/// Explain why the registry rejected the crate name, if that is why `cargo publish` failed
+///
+/// Returns false if the output of `cargo publish` does not contain a known name conflict error
+fn report_name_conflict(
+    package_name: &str,
+    registry: &registry::Registry,
+    stderr: &[String],
+) -> bool {
+    let output = stderr.join("\n").to_lowercase();
+    let is_crates_io = registry.name == registry::CRATES_IO;
+    if output.contains("you don't seem to be an owner") || output.contains("is already taken") {
+        eprintln!(
+            "{}: the name `{package_name}` is already used by a crate on the registry `{}` that you do not own",
+            "error".red().bold(),
+            registry.name
+        );
+        if is_crates_io {
+            eprintln!(
+                "crates.io never reassigns names on its own, even for unused crates. \
+                 If you think the name is squatted, contact the owners listed on \
+                 https://crates.io/crates/{package_name} and ask them to add you as owner. \
+                 See https://crates.io/policies#squatting for the policy on name squatting"
+            );
+        }
+        eprintln!(
+            "If you are an owner already, accept the pending ownership invitation first. \
+             Otherwise choose a different name for the crate"
+        );
+        return true;
+    }
+    if let Some(idx) = output.find("previously named") {
+        let existing = output[idx..].split('`').nth(1).unwrap_or(package_name);
+        eprintln!(
+            "{}: the name `{package_name}` conflicts with the existing crate `{existing}`. \
+             Registries treat `-` and `_` as well as upper and lower case as equal in crate names, \
+             so choose a different name or publish under the existing name",
+            "error".red().bold()
+        );
+        return true;
+    }
+    if output.contains("reserved name") || output.contains("is reserved") {
+        eprintln!(
+            "{}: the name `{package_name}` is reserved by the registry `{}`, e.g. because it matches \
+             a Rust keyword, a standard library crate or a Windows device name. Choose a different name for the crate",
+            "error".red().bold(),
+            registry.name
+        );
+        return true;
+    }
+    false
+}
+
+/// Warn about `RUSTFLAGS` that tie the verification build to the current machine
+fn check_rustflags() {
+    let rustflags = std::env::var("CARGO_ENCODED_RUSTFLAGS")
+        .map(|flags| flags.split('\x1f').map(ToOwned::to_owned).collect())
+        .or_else(|_| {
+            std::env::var("RUSTFLAGS")
+                .map(|flags| flags.split_whitespace().map(ToOwned::to_owned).collect())
+        })
+        .unwrap_or_else(|_| Vec::<String>::new());
+    // `-C target-cpu=native` can be passed as one or as two arguments
+    let cpu_specific_flags = rustflags
+        .iter()
+        .zip(std::iter::once("").chain(rustflags.iter().map(|f| f.as_str())))
+        .filter(|(flag, previous)| {
+            let codegen_option = flag
+                .strip_prefix("-C")
+                .map(|o| o.trim_start())
+                .or_else(|| (*previous == "-C").then_some(flag.as_str()))
+                .or_else(|| flag.strip_prefix("--codegen="));
+            codegen_option
+                .is_some_and(|o| o.starts_with("target-cpu") || o.starts_with("target-feature"))
+        })
+        .map(|(flag, _)| flag.as_str())
+        .collect::<Vec<_>>();
+    if !cpu_specific_flags.is_empty() {
+        eprintln!(
+            "{}: `RUSTFLAGS` contains CPU specific flags ({}) which affect the verification build. \
+             Users of the published crate likely build it for different CPUs. \
+             Pass `--ignore-rustflags` to silence this warning",
+            "warning".yellow(),
+            cpu_specific_flags
+                .iter()
+                .map(|f| format!("`{f}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
+
+/// The artifacts of the verification build that are used after it finished
+struct VerificationBuild {
+    /// The `Cargo.lock` file cargo generated for the packaged crate
+    lock_file_content: String,
+    /// The packaged `.crate` file
+    crate_file: Vec<u8>,
+}
+
+/// Run the command while forwarding its stderr output
+///
+/// Returns the exit status and all lines of the stderr output without color codes
+fn run_capturing_stderr(
+    command: &mut Command,
+) -> std::io::Result<(std::process::ExitStatus, Vec<String>)> {
+    // cargo does not color piped output on its own
+    if colored::control::SHOULD_COLORIZE.should_colorize() {
+        command.env("CARGO_TERM_COLOR", "always");
+    }
+    let mut child = command.stderr(Stdio::piped()).spawn()?;
+    let ansi_escape = regex::Regex::new(r"\x1b\[[0-9;]*m").expect("The regex is valid");
+    let mut lines = Vec::new();
+    let stderr = child.stderr.take().expect("stderr is piped");
+    for line in std::io::BufRead::lines(std::io::BufReader::new(stderr)) {
+        let line = line?;
+        eprintln!("{line}");
+        lines.push(ansi_escape.replace_all(&line, "").into_owned());
+    }
+    Ok((child.wait()?, lines))
+}
+
+/// Run the command while forwarding its stderr output
+///
+/// Returns the exit status and all lines of the stderr output that are cargo warnings
+fn run_capturing_warnings(
+    command: &mut Command,
+) -> std::io::Result<(std::process::ExitStatus, Vec<String>)> {
+    let (status, lines) = run_capturing_stderr(command)?;
+    let warnings = lines
+        .iter()
+        .filter_map(|line| line.strip_prefix("warning:"))
+        .map(|warning| warning.trim().to_owned())
+        .collect();
+    Ok((status, warnings))
+}
+
+/// Run `cargo publish --dry-run`
+///
+/// With `allowed_cargo_warnings` the build fails for any cargo warning
+/// that does not match one of the given patterns
+fn run_verification_build(
+    target_directory: &Path,
+    package_name: &str,
+    package_version: &cargo_metadata::semver::Version,
+    allowed_cargo_warnings: Option<&[regex::Regex]>,
+) -> Result<VerificationBuild, i32> {
+    let mut dry_run_command = cargo_command();
+
+    // `cargo publish --dry-run` looks up the version in the registry even in offline mode,
+    // `cargo package` performs the same verification build without it
+    if is_no_network() {
+        dry_run_command.arg("package");
+    } else {
+        dry_run_command.arg("publish").arg("--dry-run");
+    }
+    dry_run_command.stderr(Stdio::inherit());
+    if is_stdout_reserved() {
+        dry_run_command.stdout(std::io::stderr());
+    } else {
+        dry_run_command.stdout(Stdio::inherit());
+    }
+
+    // append all the other flags
+    for arg in cargo_arguments().into_iter().filter(|c| c != "--dry-run") {
+        dry_run_command.arg(arg);
+    }
+    // a fresh target directory guarantees a build from scratch, like for users of the crate
+    let clean_target_directory = std::env::args()
+        .any(|c| c == "--clean-verify")
+        .then(|| std::env::temp_dir().join(format!("cargo-safe-publish-{}", std::process::id())));
+    if let Some(clean_target_directory) = &clean_target_directory {
+        print_status(format_args!(
+            "Use the isolated target directory `{}` for the verification build",
+            clean_target_directory.display()
+        ));
+        dry_run_command.env("CARGO_TARGET_DIR", clean_target_directory);
+    }
+    let remove_clean_target_directory = || {
+        if let Some(clean_target_directory) = &clean_target_directory
+            && let Err(e) = std::fs::remove_dir_all(clean_target_directory)
+        {
+            eprintln!(
+                "{}: failed to remove the isolated target directory `{}`: {e}",
+                "warning".yellow(),
+                clean_target_directory.display()
+            );
+        }
+    };
+    let target_directory = clean_target_directory
+        .as_deref()
+        .unwrap_or(target_directory);
+    print_command("verification build", &dry_run_command);
+    let (dry_run_status, warnings) = if allowed_cargo_warnings.is_some() {
+        match run_capturing_warnings(&mut dry_run_command) {
+            Ok((status, warnings)) => (Ok(status), warnings),
+            Err(e) => (Err(e), Vec::new()),
+        }
+    } else {
+        (dry_run_command.status(), Vec::new())
+    };
+    match dry_run_status {
+        Err(e) => {
+            eprintln!("{}: dry run failed: {e}", "error".red().bold());
+            remove_clean_target_directory();
+            return Err(1);
+        }
+        Ok(s) if !s.success() => {
+            eprintln!(
+                "{}: dry run returned a non-zero exist code, check the output above for details",
+                "error".red().bold()
+            );
+            remove_clean_target_directory();
+            return Err(s.code().unwrap_or(1));
+        }
+        Ok(_) => {}
+    }
+
+    // cargo should remove these files on it's own on the new call to `cargo publish` with the same version
+    // but we better make sure that they are gone instead of relying on that behavior
+    let unpacked_target_package = target_directory
+        .join("package")
+        .join(format!("{package_name}-{package_version}"));
+    let target_package = target_directory
+        .join("package")
+        .join(format!("{package_name}-{package_version}.crate"));
+
+    let lock_file = unpacked_target_package.join("Cargo.lock");
+    let lock_file_content =
+        std::fs::read_to_string(lock_file).expect("Failed to read new lock file");
+    let crate_file = std::fs::read(&target_package).expect("Failed to read the packed crate");
+
+    let is_confirmed =
+        !std::env::args().any(|c| c == "--inspect") || inspect_package(&unpacked_target_package);
+
+    if clean_target_directory.is_some() {
+        remove_clean_target_directory();
+    } else {
+        std::fs::remove_dir_all(unpacked_target_package).expect(
+            "Failed to remove unpacked package from the target directory during the verification build",
+        );
+        std::fs::remove_file(target_package).expect(
+            "Failed to remove the packed crate from the target directory during the verification build",
+        );
+    }
+
+    if !is_confirmed {
+        eprintln!(
+            "{}: not publishing `{package_name}` ({package_version}) after the inspection",
+            "error".red().bold()
+        );
+        std::process::exit(1);
+    }
+
+    if let Some(allowed_cargo_warnings) = allowed_cargo_warnings {
+        let denied_warnings = warnings
+            .iter()
+            .filter(|w| !allowed_cargo_warnings.iter().any(|p| p.is_match(w)))
+            .collect::<Vec<_>>();
+        if !denied_warnings.is_empty() {
+            eprintln!(
+                "{}: the verification build emitted {} warnings, which are denied by `--deny-cargo-warning`:",
+                "error".red().bold(),
+                denied_warnings.len()
+            );
+            for warning in denied_warnings {
+                eprintln!("  * {warning}");
+            }
+            return Err(1);
+        }
+    }
+
+    Ok(VerificationBuild {
+        lock_file_content,
+        crate_file,
+    })
+}
+
+/// Find an installed rustup toolchain matching the given `rust-version`
+fn find_msrv_toolchain(rust_version: &cargo_metadata::semver::Version) -> Option<String> {
+    let output = Command::new("rustup")
+        .args(["toolchain", "list"])
+        .stderr(Stdio::inherit())
+        .output()
+        .ok()?;
+    let short_version = format!("{}.{}", rust_version.major, rust_version.minor);
+    let full_version = rust_version.to_string();
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .find(|toolchain| {
+            // toolchains are named like `1.70-x86_64-unknown-linux-gnu` or `1.70.0-x86_64-unknown-linux-gnu`
+            let channel = toolchain.split('-').next().unwrap_or_default();
+            channel == full_version || (rust_version.patch == 0 && channel == short_version)
+        })
+        .map(ToOwned::to_owned)
+}
+
+/// Check that the package builds with the toolchain of its declared `rust-version`
+fn run_msrv_build(package: &cargo_metadata::Package) -> Result<(), i32> {
+    let Some(rust_version) = &package.rust_version else {
+        print_status(format_args!(
+            "Skip the MSRV build as `{}` does not declare a `rust-version`",
+            package.name
+        ));
+        return Ok(());
+    };
+    let Some(toolchain) = find_msrv_toolchain(rust_version) else {
+        eprintln!(
+            "{}: no toolchain for the declared `rust-version` {rust_version} is installed, \
+             install it via `rustup toolchain install {}.{}`",
+            "error".red().bold(),
+            rust_version.major,
+            rust_version.minor
+        );
+        return Err(1);
+    };
+
+    let mut msrv_command = toolchain_cargo_command(&toolchain);
+    msrv_command
+        .arg("check")
+        .arg("--manifest-path")
+        .arg(&package.manifest_path)
+        .stderr(Stdio::inherit())
+        .stdout(Stdio::inherit());
+    print_command("MSRV build", &msrv_command);
+    match msrv_command.status() {
+        Err(e) => {
+            eprintln!("{}: MSRV build failed: {e}", "error".red().bold());
+            Err(1)
+        }
+        Ok(s) if !s.success() => {
+            eprintln!(
+                "{}: the package does not build with its declared `rust-version` {rust_version} (toolchain `{toolchain}`), \
+                 check the output above for details",
+                "error".red().bold()
+            );
+            Err(s.code().unwrap_or(1))
+        }
+        Ok(_) => Ok(()),
+    }
+}
+
+/// Returns true if any rust source file of the package contains an `unsafe` block
+fn package_contains_unsafe(package_root: &cargo_metadata::camino::Utf8Path) -> bool {
+    ignore::WalkBuilder::new(package_root)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "rs"))
+        .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+        .any(|content| {
+            content.match_indices("unsafe").any(|(idx, _)| {
+                content[idx + "unsafe".len()..]
+                    .trim_start()
+                    .starts_with('{')
+            })
+        })
+}
+
+/// Find a cargo invocation that has miri available
+///
+/// Returns the toolchain override necessary to run miri, if any
+fn find_miri() -> Option<Option<&'static str>> {
+    [None, Some("nightly")].into_iter().find(|toolchain| {
+        let mut command = match toolchain {
+            Some(toolchain) => toolchain_cargo_command(toolchain),
+            None => cargo_command(),
+        };
+        command
+            .arg("miri")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success())
+    })
+}
+
+/// Run the test suite of the package under miri to detect undefined behavior in `unsafe` code
+///
+/// `cargo miri` interprets code, so there is no `check` subcommand. We run the tests instead
+fn run_miri_check(package: &cargo_metadata::Package) -> Result<CheckResult, i32> {
+    let package_root = package.manifest_path.parent().unwrap();
+    if !package_contains_unsafe(package_root) {
+        print_status(format_args!(
+            "Skip the miri check as `{}` does not contain any `unsafe` block",
+            package.name
+        ));
+        return Ok(CheckResult::Skipped);
+    }
+    let Some(miri) = find_miri() else {
+        eprintln!(
+            "{}: skip the miri check as miri is not installed, \
+             install it via `rustup +nightly component add miri`",
+            "warning".yellow()
+        );
+        return Ok(CheckResult::Skipped);
+    };
+
+    let mut miri_command = match miri {
+        Some(toolchain) => toolchain_cargo_command(toolchain),
+        None => cargo_command(),
+    };
+    miri_command
+        .arg("miri")
+        .arg("test")
+        .arg("--manifest-path")
+        .arg(&package.manifest_path)
+        .stderr(Stdio::inherit())
+        .stdout(Stdio::inherit());
+    print_command("miri check", &miri_command);
+    match miri_command.status() {
+        Err(e) => {
+            eprintln!("{}: miri check failed: {e}", "error".red().bold());
+            Err(1)
+        }
+        Ok(s) if !s.success() => {
+            eprintln!(
+                "{}: miri check returned a non-zero exit code, check the output above for details",
+                "error".red().bold()
+            );
+            Err(s.code().unwrap_or(1))
+        }
+        Ok(_) => Ok(CheckResult::Passed),
+    }
+}
+
+/// Check that every combination of features compiles via `cargo hack --feature-powerset`
+///
+/// The combinations are limited to `--feature-powerset-depth` features if passed
+fn run_feature_powerset_check(package: &cargo_metadata::Package) -> Result<CheckResult, i32> {
+    let is_installed = cargo_command()
+        .args(["hack", "--version"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
+    if !is_installed {
+        eprintln!(
+            "{}: skip the feature powerset check as cargo-hack is not installed, \
+             install it via `cargo install cargo-hack`",
+            "warning".yellow()
+        );
+        return Ok(CheckResult::Skipped);
+    }
+
+    let mut hack_command = cargo_command();
+    hack_command.arg("hack").arg("--feature-powerset");
+    if let Some(depth) = feature_powerset_depth() {
+        hack_command.arg("--depth").arg(depth.to_string());
+    }
+    hack_command
+        .arg("check")
+        .arg("--manifest-path")
+        .arg(&package.manifest_path)
+        .stdout(Stdio::inherit());
+    print_command("feature powerset check", &hack_command);
+    let mut child = match hack_command.stderr(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!(
+                "{}: feature powerset check failed: {e}",
+                "error".red().bold()
+            );
+            return Err(1);
+        }
+    };
+    // cargo-hack announces each combination with "running `cargo check <flags>` on <package>"
+    // and stops at the first one that fails
+    let running =
+        regex::Regex::new(r"running `[^`]*?\bcheck\b([^`]*)`").expect("The regex is valid");
+    let mut current_features = None;
+    let stderr = child.stderr.take().expect("stderr is piped");
+    for line in std::io::BufRead::lines(std::io::BufReader::new(stderr)).map_while(Result::ok) {
+        eprintln!("{line}");
+        if let Some(captures) = running.captures(&line) {
+            current_features = Some(captures[1].trim().to_owned());
+        }
+    }
+    match child.wait() {
+        Err(e) => {
+            eprintln!(
+                "{}: feature powerset check failed: {e}",
+                "error".red().bold()
+            );
+            Err(1)
+        }
+        Ok(s) if !s.success() => {
+            match current_features.as_deref() {
+                Some("") => eprintln!(
+                    "{}: `{}` does not compile with the default features",
+                    "error".red().bold(),
+                    package.name
+                ),
+                Some(features) => eprintln!(
+                    "{}: `{}` does not compile with `{features}`",
+                    "error".red().bold(),
+                    package.name
+                ),
+                None => eprintln!(
+                    "{}: feature powerset check returned a non-zero exit code, check the output above for details",
+                    "error".red().bold()
+                ),
+            }
+            Err(s.code().unwrap_or(1))
+        }
+        Ok(_) => Ok(CheckResult::Passed),
+    }
+}
+
+/// Build and run each example of the package, except for those passed via `--skip-example`
+///
+/// Examples are built before they are run, so the timeout only applies to the run itself
+fn run_example_checks(package: &cargo_metadata::Package) -> Result<(), i32> {
+    let skipped = flag_values("--skip-example");
+    let timeout = example_timeout();
+    for example in package.targets.iter().filter(|t| t.is_example()) {
+        if skipped.contains(&example.name) {
+            print_status(format_args!("Skip the example `{}`", example.name));
+            continue;
+        }
+        for step in ["build", "run"] {
+            let mut example_command = cargo_command();
+            example_command
+                .arg(step)
+                .arg("--manifest-path")
+                .arg(&package.manifest_path)
+                .arg("--example")
+                .arg(&example.name)
+                .stderr(Stdio::inherit())
+                .stdout(Stdio::inherit());
+            if !example.required_features.is_empty() {
+                example_command
+                    .arg("--features")
+                    .arg(example.required_features.join(","));
+            }
+            print_command(&format!("example {step}"), &example_command);
+            let mut child = example_command.spawn().map_err(|e| {
+                eprintln!(
+                    "{}: failed to {step} the example `{}`: {e}",
+                    "error".red().bold(),
+                    example.name
+                );
+                1
+            })?;
+            let started = std::time::Instant::now();
+            let status = loop {
+                if let Some(status) = child.try_wait().expect("Failed to wait for cargo") {
+                    break status;
+                }
+                if step == "run" && started.elapsed() > timeout {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    eprintln!(
+                        "{}: the example `{}` did not finish within {} seconds, \
+                         pass `--example-timeout` to allow more time or `--skip-example {}` to skip it",
+                        "error".red().bold(),
+                        example.name,
+                        timeout.as_secs(),
+                        example.name
+                    );
+                    return Err(1);
+                }
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            };
+            if !status.success() {
+                eprintln!(
+                    "{}: failed to {step} the example `{}`, check the output above for details",
+                    "error".red().bold(),
+                    example.name
+                );
+                return Err(status.code().unwrap_or(1));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Open the unpacked package in the file manager and ask whether to continue
+fn inspect_package(unpacked_package: &Path) -> bool {
+    let file_manager = if cfg!(target_os = "macos") {
+        Some("open")
+    } else if cfg!(target_os = "windows") {
+        Some("explorer")
+    } else if cfg!(unix) {
+        Some("xdg-open")
+    } else {
+        None
+    };
+    print_status(format_args!(
+        "The packaged content is available at `{}`",
+        unpacked_package.display()
+    ));
+    if let Some(file_manager) = file_manager
+        && let Err(e) = Command::new(file_manager)
+            .arg(unpacked_package)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+    {
+        eprintln!(
+            "{}: failed to open `{file_manager}`: {e}",
+            "warning".yellow()
+        );
+    }
+    confirm("Continue with publish?")
+}
+
+/// Ask the user a yes/no question on the terminal, defaults to no
+fn confirm(question: &str) -> bool {
+    if !std::io::stdin().is_terminal() {
+        return false;
+    }
+    eprint!("{question} [y/N] ");
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .expect("Failed to read from stdin");
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// Ask whether to continue after the change threshold was exceeded,
+/// explains why we stop if there is nobody to ask
+fn confirm_change_threshold() -> bool {
+    if !std::io::stdin().is_terminal() {
+        eprintln!(
+            "{}: change threshold exceeded and no terminal to confirm; \
+             rerun interactively or raise --change-threshold",
+            "error".red().bold()
+        );
+        return false;
+    }
+    confirm("Continue with publish?")
+}
+
+/// Make sure the standard library for the given target is installed,
+/// offers to install it via rustup otherwise
+fn ensure_target_installed(target: &str) -> bool {
+    let installed = Command::new("rustup")
+        .args(["target", "list", "--installed"])
+        .stderr(Stdio::inherit())
+        .output();
+    let Ok(installed) = installed else {
+        // without rustup we cannot know, so let cargo report any problem
+        return true;
+    };
+    if String::from_utf8_lossy(&installed.stdout)
+        .lines()
+        .any(|t| t.trim() == target)
+    {
+        return true;
+    }
+    if !confirm(&format!(
+        "The target `{target}` is not installed. Install it via `rustup target add {target}`?"
+    )) {
+        eprintln!(
+            "{}: the target `{target}` is not installed, install it via `rustup target add {target}`",
+            "error".red().bold()
+        );
+        return false;
+    }
+    Command::new("rustup")
+        .args(["target", "add", target])
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Check that the package compiles for all targets passed via `--verify-target`
+fn run_target_checks(package: &cargo_metadata::Package, targets: &[String]) -> Result<(), i32> {
+    let mut result = Ok(());
+    for target in targets {
+        if !ensure_target_installed(target) {
+            result = Err(1);
+            continue;
+        }
+        let mut check_command = cargo_command();
+        check_command
+            .arg("check")
+            .arg("--manifest-path")
+            .arg(&package.manifest_path)
+            .arg("--target")
+            .arg(target)
+            .stderr(Stdio::inherit())
+            .stdout(Stdio::inherit());
+        print_command(&format!("check for `{target}`"), &check_command);
+        match check_command.status() {
+            Err(e) => {
+                eprintln!("{}: check for `{target}` failed: {e}", "error".red().bold());
+                result = Err(1);
+            }
+            Ok(s) if !s.success() => {
+                eprintln!(
+                    "{}: the package does not compile for `{target}`, check the output above for details",
+                    "error".red().bold()
+                );
+                result = Err(s.code().unwrap_or(1));
+            }
+            Ok(_) => {}
+        }
+    }
+    result
+}
+
+fn get_git_root(package_root: &Path) -> Option<&Path> {
+    let mut check_dir = Some(package_root);
+    loop {
+        let local_check_dir = check_dir?;
+        if local_check_dir.join(".git").exists() {
+            return Some(local_check_dir);
+        } else {
+            check_dir = local_check_dir.parent();
+        }
+    }
+}
+
+/// All workspace packages that contain files changed between the given git tag and `HEAD`
+fn packages_changed_since<'a>(
+    metadata: &'a cargo_metadata::Metadata,
+    package_root: &Path,
+    tag: &str,
+) -> Vec<&'a cargo_metadata::Package> {
+    let Some(git_root) = get_git_root(package_root) else {
+        eprintln!(
+            "{}: `--since-tag` requires the package to be part of a git repository",
+            "error".red().bold()
+        );
+        std::process::exit(1);
+    };
+    let repo = gix::open(git_root).expect("Could not open git repo");
+    let tree_of = |rev: &str| {
+        repo.rev_parse_single(rev)
+            .ok()
+            .and_then(|id| id.object().ok()?.peel_to_tree().ok())
+            .unwrap_or_else(|| {
+                eprintln!(
+                    "{}: could not resolve `{rev}` to a tree",
+                    "error".red().bold()
+                );
+                std::process::exit(1);
+            })
+    };
+    let tag_tree = tree_of(&format!("refs/tags/{tag}"));
+    let head_tree = tree_of("HEAD");
+    let changes = repo
+        .diff_tree_to_tree(&tag_tree, &head_tree, None)
+        .expect("Failed to diff the git trees");
+    let changed_paths = changes
+        .iter()
+        .flat_map(|change| [change.location(), change.source_location()])
+        .map(|path| git_root.join(gix::path::from_bstr(path)))
+        .collect::<Vec<_>>();
+
+    let workspace_packages = metadata
+        .packages
+        .iter()
+        .filter(|p| metadata.workspace_members.contains(&p.id))
+        .collect::<Vec<_>>();
+    workspace_packages
+        .iter()
+        .copied()
+        .filter(|package| {
+            changed_paths
+                .iter()
+                .any(|path| owns_path(&workspace_packages, package, path))
+        })
+        .collect()
+}
+
+/// Whether the given absolute path belongs to the package
+///
+/// Nested packages own their files, not the package containing them
+fn owns_path(
+    workspace_packages: &[&cargo_metadata::Package],
+    package: &cargo_metadata::Package,
+    path: &Path,
+) -> bool {
+    let root = package.manifest_path.parent().unwrap().as_std_path();
+    path.starts_with(root)
+        && !workspace_packages.iter().any(|other| {
+            let other_root = other.manifest_path.parent().unwrap().as_std_path();
+            other_root != root && other_root.starts_with(root) && path.starts_with(other_root)
+        })
+}
+
+/// Warn about publishable workspace members other than the published one
+/// that were changed by commits since the git tag of their current version
+///
+/// Tags are expected to be named `<name>-v<version>`, `<name>-<version>`,
+/// `<name>@<version>` or `v<version>`
+fn report_unpublished_changes(
+    metadata: &cargo_metadata::Metadata,
+    package_to_publish: &cargo_metadata::Package,
+) {
+    let Some(git_root) = get_git_root(metadata.workspace_root.as_std_path()) else {
+        eprintln!(
+            "{}: `--detect-unpublished-changes` requires the workspace to be part of a git repository",
+            "warning".yellow()
+        );
+        return;
+    };
+    let repo = gix::open(git_root).expect("Could not open git repo");
+    let head = repo
+        .head_id()
+        .expect("Failed to get the HEAD commit")
+        .detach();
+    let workspace_packages = metadata
+        .packages
+        .iter()
+        .filter(|p| metadata.workspace_members.contains(&p.id))
+        .collect::<Vec<_>>();
+    // `publish = false` is represented as an empty list of registries
+    let others = workspace_packages.iter().filter(|p| {
+        p.id != package_to_publish.id && p.publish.as_ref().is_none_or(|r| !r.is_empty())
+    });
+
+    for package in others {
+        let tags = [
+            format!("{}-v{}", package.name, package.version),
+            format!("{}-{}", package.name, package.version),
+            format!("{}@{}", package.name, package.version),
+            format!("v{}", package.version),
+        ];
+        let Some(tag) = tags.iter().find_map(|tag| {
+            repo.rev_parse_single(format!("refs/tags/{tag}").as_str())
+                .ok()?
+                .object()
+                .ok()?
+                .peel_to_commit()
+                .ok()
+        }) else {
+            print_status(format_args!(
+                "{}: no git tag found for `{}` ({}), expected one of {}",
+                "note".cyan(),
+                package.name,
+                package.version,
+                tags.map(|t| format!("`{t}`")).join(", ")
+            ));
+            continue;
+        };
+
+        let commits = repo
+            .rev_walk([head])
+            .with_hidden([tag.id])
+            .all()
+            .expect("Failed to walk the git history")
+            .filter_map(|info| {
+                let commit = info.ok()?.object().ok()?;
+                let tree = commit.tree().ok()?;
+                let parent_tree = match commit.parent_ids().next() {
+                    Some(parent) => parent.object().ok()?.peel_to_tree().ok()?,
+                    None => repo.empty_tree(),
+                };
+                let changes = repo
+                    .diff_tree_to_tree(&parent_tree, &tree, None)
+                    .expect("Failed to diff the git trees");
+                let is_relevant = changes
+                    .iter()
+                    .flat_map(|change| [change.location(), change.source_location()])
+                    .any(|path| {
+                        owns_path(
+                            &workspace_packages,
+                            package,
+                            &git_root.join(gix::path::from_bstr(path)),
+                        )
+                    });
+                let summary = commit.message().ok()?.summary().to_string();
+                is_relevant.then(|| (commit.id().shorten_or_id().to_string(), summary))
+            })
+            .collect::<Vec<_>>();
+        if commits.is_empty() {
+            continue;
+        }
+        eprintln!(
+            "{}: `{}` ({}) has {} commits since its last release that are not published:",
+            "warning".yellow(),
+            package.name,
+            package.version,
+            commits.len()
+        );
+        for (id, summary) in commits {
+            eprintln!("  {} {summary}", id.bold());
+        }
+    }
+}
+
+fn git_head_commit(package_root: &Path) -> Option<String> {
+    let git_root = get_git_root(package_root)?;
+    let repo = gix::open(git_root).ok()?;
+    let head = repo.head_id().ok()?;
+    Some(head.to_string())
+}
+
+/// The pattern the `HEAD` commit message needs to match
+///
+/// `--require-commit-format` wins over the configuration file which wins over a `.commitmsgrc` file
+fn commit_format_pattern(
+    config: &config::Config,
+    package_root: &cargo_metadata::camino::Utf8Path,
+) -> Option<String> {
+    flag_value("--require-commit-format")
+        .or_else(|| config.require_commit_format.clone())
+        .or_else(|| {
+            let git_root = get_git_root(package_root.as_std_path());
+            [Some(package_root.as_std_path()), git_root]
+                .into_iter()
+                .flatten()
+                .filter_map(|dir| std::fs::read_to_string(dir.join(".commitmsgrc")).ok())
+                .find_map(|content| {
+                    content
+                        .lines()
+                        .map(str::trim)
+                        .find(|l| !l.is_empty())
+                        .map(ToOwned::to_owned)
+                })
+        })
+}
+
+/// Returns false if the message of the `HEAD` commit does not match the given pattern
+fn check_commit_format(package_root: &cargo_metadata::camino::Utf8Path, pattern: &str) -> bool {
+    let regex = regex::Regex::new(pattern).unwrap_or_else(|e| {
+        eprintln!(
+            "{}: invalid commit message pattern `{pattern}`: {e}",
+            "error".red().bold()
+        );
+        std::process::exit(1);
+    });
+    let Some(git_root) = get_git_root(package_root.as_std_path()) else {
+        eprintln!(
+            "{}: cannot check the commit message format as `{package_root}` is not part of a git repository",
+            "error".red().bold()
+        );
+        return false;
+    };
+    let repo = gix::open(git_root).expect("Could not open git repo");
+    let commit = repo.head_commit().expect("Failed to get the HEAD commit");
+    let message = commit
+        .message_raw()
+        .expect("Failed to read the HEAD commit message")
+        .to_string();
+    let message = message.trim_end();
+    if regex.is_match(message) {
+        return true;
+    }
+    eprintln!(
+        "{}: the message of the HEAD commit does not match the required format `{}`:",
+        "error".red().bold(),
+        pattern.bold()
+    );
+    eprintln!();
+    for line in message.lines() {
+        eprintln!("    {line}");
+    }
+    false
+}
+
+/// Warn if `package.include` and `package.exclude` are both set,
+/// as cargo ignores `package.exclude` entirely in that case
+fn warn_include_and_exclude(
+    package_root: &cargo_metadata::camino::Utf8Path,
+    workspace_root: &cargo_metadata::camino::Utf8Path,
+) {
+    let manifest = read_package_manifest(package_root, workspace_root);
+    let Some(package) = manifest.package else {
+        return;
+    };
+    if !package.include().is_empty() && !package.exclude().is_empty() {
+        eprintln!(
+            "{}: both `package.include` and `package.exclude` are set in `{package_root}/Cargo.toml`. \
+             Cargo ignores `package.exclude` entirely in this case, so the excluded files are only \
+             left out if `package.include` does not match them. Remove `package.exclude` and make \
+             `package.include` list only the files to publish, or remove `package.include` to \
+             publish everything except the excluded files. \
+             See https://doc.rust-lang.org/cargo/reference/manifest.html#the-exclude-and-include-fields",
+            "warning".yellow()
+        );
+    }
+}
+
+/// Read the `Cargo.toml` of the package with all fields inherited from the workspace resolved
+///
+/// cargo_toml would use the closest parent directory containing a `[workspace]`
+/// table, which is not necessarily the workspace the package belongs to,
+/// so the workspace root is taken from `cargo metadata`
+fn read_package_manifest(
+    package_root: &cargo_metadata::camino::Utf8Path,
+    workspace_root: &cargo_metadata::camino::Utf8Path,
+) -> cargo_toml::Manifest {
+    let manifest_path = package_root.join("Cargo.toml");
+    let manifest = if package_root == workspace_root {
+        cargo_toml::Manifest::from_path(&manifest_path)
+    } else {
+        let workspace = cargo_toml::Manifest::from_path(workspace_root.join("Cargo.toml"))
+            .expect("Failed to read the `Cargo.toml` of the workspace");
+        std::fs::read(&manifest_path)
+            .map_err(cargo_toml::Error::from)
+            .and_then(|content| cargo_toml::Manifest::from_slice(&content))
+            .and_then(|mut manifest| {
+                manifest
+                    .complete_from_path_and_workspace(
+                        manifest_path.as_std_path(),
+                        Some((&workspace, workspace_root.as_std_path())),
+                    )
+                    .map(|()| manifest)
+            })
+    };
+    manifest.expect("Failed to read `Cargo.toml`")
+}
+
+/// The way the git status of the package is determined
+#[derive(Debug, Clone, Copy)]
+enum GitBackend {
+    Gix,
+    /// Shell out to `git status --porcelain=v1`, for repositories `gix` can't handle
+    Subprocess,
+}
+
+fn git_backend() -> GitBackend {
+    match flag_value("--git-backend").as_deref() {
+        None | Some("gix") => GitBackend::Gix,
+        Some("subprocess") => GitBackend::Subprocess,
+        Some(backend) => {
+            eprintln!(
+                "{}: invalid value `{backend}` for `--git-backend`, expected `gix` or `subprocess`",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Run git with the given arguments in the given directory and return its stdout
+fn git_output(git_root: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(git_root)
+        .output()
+        .unwrap_or_else(|e| {
+            eprintln!("{}: failed to run `git`: {e}", "error".red().bold());
+            std::process::exit(1);
+        });
+    if !output.status.success() {
+        eprintln!(
+            "{}: `git {}` failed: {}",
+            "error".red().bold(),
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+        std::process::exit(1);
+    }
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Returns the committed and the local version if the only difference between the
+/// `Cargo.toml` of the package in `HEAD` and in the working directory is `package.version`
+fn uncommitted_version_bump(
+    package_root: &cargo_metadata::camino::Utf8Path,
+    git_root: &Path,
+    sub_dir: Option<&Path>,
+    backend: GitBackend,
+) -> Option<(String, String)> {
+    let path_in_repo = sub_dir
+        .map(|sub_dir| sub_dir.join("Cargo.toml"))
+        .unwrap_or_else(|| PathBuf::from("Cargo.toml"));
+    let committed = match backend {
+        GitBackend::Gix => {
+            let repo = gix::open(git_root).ok()?;
+            let tree = repo.head_commit().ok()?.tree().ok()?;
+            let entry = tree.lookup_entry_by_path(&path_in_repo).ok()??;
+            String::from_utf8(entry.object().ok()?.detach().data).ok()?
+        }
+        GitBackend::Subprocess => {
+            // git always expects `/` as separator in `<rev>:<path>`
+            let path_in_repo = path_in_repo
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            // not via `git_output` as a missing file is not an error here
+            let output = Command::new("git")
+                .args(["show", &format!("HEAD:{path_in_repo}")])
+                .current_dir(git_root)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())?;
+            String::from_utf8(output.stdout).ok()?
+        }
+    };
+    let local = std::fs::read_to_string(package_root.join("Cargo.toml")).ok()?;
+    // the parsed manifests do not contain comments or formatting, so make sure
+    // that nothing except the line containing the version changed
+    let changed_lines = committed
+        .lines()
+        .zip(local.lines())
+        .filter(|(committed, local)| committed != local)
+        .count();
+    if committed.lines().count() != local.lines().count() || changed_lines != 1 {
+        return None;
+    }
+
+    let without_version = |content: &str| {
+        let mut manifest = content.parse::<toml::Table>().ok()?;
+        let version = manifest
+            .get_mut("package")?
+            .as_table_mut()?
+            .remove("version")?;
+        Some((manifest, version.as_str()?.to_owned()))
+    };
+    let (committed, old_version) = without_version(&committed)?;
+    let (local, new_version) = without_version(&local)?;
+    (committed == local && old_version != new_version).then_some((old_version, new_version))
+}
+
+/// All changed files of the package according to `gix`, relative to the package root,
+/// together with a description of the change
+fn gix_status(
+    git_root: &Path,
+    sub_dir: Option<&Path>,
+    is_packaged: impl Fn(&Path) -> bool,
+) -> Vec<(String, String)> {
+    let sub_dir = sub_dir.map(|sub_dir| {
+        gix::diff::object::bstr::BString::new(sub_dir.as_os_str().as_encoded_bytes().to_vec())
+    });
+    // pathspecs are relative to the current directory otherwise
+    let patterns = sub_dir.iter().map(|sub_dir| {
+        let mut pathspec = gix::diff::object::bstr::BString::from(":(top)");
+        pathspec.extend_from_slice(sub_dir);
+        pathspec
+    });
+    let repo = gix::open(git_root).expect("Could not open git repo");
+    repo
+                .status(gix::progress::Discard)
+                .expect("Failed to get repo state")
+                .untracked_files(gix::status::UntrackedFiles::Files)
+                .into_iter(patterns)
+                .expect("Failed to get repo state")
+                .filter_map(|i| {
+                    let item = match i {
+                        Ok(i) => i,
                         Err(e) => return Some(Err(e)),
                     };
                     let mut path = item.location();
@@ -362,34 +2780,11 @@ fn check_git_is_dirty(package_root: &cargo_metadata::camino::Utf8Path) {
                             })
                     ) {
                         let path_to_check = <[u8] as gix::diff::object::bstr::ByteSlice>::to_path(path).expect("Valid OsStr");
-                        let is_dir = false;
-                        if let Some(includes) = &include {
-                            if !includes.matched_path_or_any_parents(path_to_check, is_dir).is_ignore() {
-                                return None;
-                            }
-                        } else if let Some(excludes) = &exclude {
-                            if excludes.matched_path_or_any_parents(path_to_check, is_dir).is_ignore() {
-                                return None;
-                            }
+                        if !is_packaged(path_to_check) {
+                            return None;
                         }
                     }
-                    let path = path.to_owned();
-                    Some(Ok((item, path)))
-                })
-                .collect::<Result<Vec<_>, _>>()
-                .expect("Failed to get repo state");
-
-        if !status.is_empty() {
-            eprintln!();
-            eprintln!(
-                "{}: {} files in the working directory contain changes \
-                         that were not yet committed into git:",
-                "error".red().bold(),
-                status.len()
-            );
-            eprintln!();
-            for (item, path) in status {
-                let modification_kind = match &item {
+                    let modification_kind = match &item {
                         gix::status::Item::IndexWorktree(
                             gix::status::index_worktree::Item::DirectoryContents { entry, .. },
                         ) => format!(" ({:?})", entry.status),
@@ -409,23 +2804,740 @@ fn check_git_is_dirty(package_root: &cargo_metadata::camino::Utf8Path) {
                         ) => " (Submodule Modified)".to_owned(),
                         _ => "".to_owned(),
                     };
-                eprintln!("{path}{modification_kind}", path = path.to_string().bold());
+                    Some(Ok((path.to_string(), modification_kind)))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .expect("Failed to get repo state")
+}
+
+/// All changed files of the package according to `git status --porcelain=v1`,
+/// relative to the package root, together with a description of the change
+fn subprocess_status(
+    git_root: &Path,
+    sub_dir: Option<&Path>,
+    is_packaged: impl Fn(&Path) -> bool,
+) -> Vec<(String, String)> {
+    let mut args = vec!["status", "--porcelain=v1", "-z", "--untracked-files=all"];
+    let sub_dir_str = sub_dir.map(|sub_dir| sub_dir.to_string_lossy());
+    if let Some(sub_dir) = &sub_dir_str {
+        args.extend(["--", sub_dir]);
+    }
+    let output = git_output(git_root, &args);
+    // each entry is `XY <path>`, renames and copies are followed by an additional entry
+    // containing the original path
+    let mut entries = output.split('\0').filter(|entry| !entry.is_empty());
+    let mut status = Vec::new();
+    while let Some(entry) = entries.next() {
+        let Some((code, path)) = entry.split_at_checked(3) else {
+            continue;
+        };
+        if code.contains(['R', 'C']) {
+            entries.next();
+        }
+        let path = Path::new(path);
+        let path = sub_dir
+            .and_then(|sub_dir| path.strip_prefix(sub_dir).ok())
+            .unwrap_or(path);
+        if !is_packaged(path) {
+            continue;
+        }
+        let modification_kind = match code.trim() {
+            "??" => " (Untracked)",
+            code if code.contains('D') => " (Deleted)",
+            code if code.contains('R') => " (Renamed)",
+            code if code.contains('A') => " (Added)",
+            code if code.contains('M') => " (Modified)",
+            _ => "",
+        };
+        status.push((path.display().to_string(), modification_kind.to_owned()));
+    }
+    status
+}
+
+/// Returns false if the package contains changes that are not committed yet
+fn check_git_is_dirty(
+    package_root: &cargo_metadata::camino::Utf8Path,
+    workspace_root: &cargo_metadata::camino::Utf8Path,
+) -> bool {
+    if let Some(git_root) = get_git_root(package_root.as_std_path()) {
+        let manifest = read_package_manifest(package_root, workspace_root);
+        let include = manifest
+            .package
+            .as_ref()
+            .map(|p| p.include())
+            .and_then(|i| (!i.is_empty()).then_some(i));
+        let exclude = manifest
+            .package
+            .as_ref()
+            .map(|p| p.exclude())
+            .and_then(|e| (!e.is_empty()).then_some(e));
+
+        let include = include.map(|p| {
+            p.iter()
+                .fold(
+                    ignore::gitignore::GitignoreBuilder::new(package_root),
+                    |mut builder, i| {
+                        builder.add_line(None, i).unwrap();
+                        builder
+                    },
+                )
+                .build()
+                .unwrap()
+        });
+        let exclude = exclude.map(|p| {
+            p.iter()
+                .fold(
+                    ignore::gitignore::GitignoreBuilder::new(package_root),
+                    |mut builder, i| {
+                        builder.add_line(None, i).unwrap();
+                        builder
+                    },
+                )
+                .build()
+                .unwrap()
+        });
+
+        let is_packaged = |path: &Path| {
+            if let Some(includes) = &include {
+                includes
+                    .matched_path_or_any_parents(path, false)
+                    .is_ignore()
+            } else if let Some(excludes) = &exclude {
+                !excludes
+                    .matched_path_or_any_parents(path, false)
+                    .is_ignore()
+            } else {
+                true
+            }
+        };
+        let sub_dir = (package_root != git_root).then(|| {
+            package_root
+                .as_std_path()
+                .strip_prefix(git_root)
+                .expect("The package_root path is a child path or equivalent to the git root path")
+        });
+
+        let backend = git_backend();
+        let is_shallow = match backend {
+            GitBackend::Gix => gix::open(git_root)
+                .expect("Could not open git repo")
+                .is_shallow(),
+            GitBackend::Subprocess => {
+                git_output(git_root, &["rev-parse", "--is-shallow-repository"]) == "true\n"
+            }
+        };
+        if is_shallow {
+            if std::env::args().any(|c| c == "--require-full-clone") {
+                eprintln!(
+                    "{}: the git repository is a shallow clone, fetch the full history \
+                     via `git fetch --unshallow`",
+                    "error".red().bold()
+                );
+                return false;
+            }
+            eprintln!(
+                "{}: the git repository is a shallow clone, \
+                 checks that depend on the git history might be incomplete",
+                "warning".yellow()
+            );
+        }
+        let status = match backend {
+            GitBackend::Gix => gix_status(git_root, sub_dir, is_packaged),
+            GitBackend::Subprocess => subprocess_status(git_root, sub_dir, is_packaged),
+        };
+
+        if !status.is_empty()
+            && status.iter().all(|(path, _)| path == "Cargo.toml")
+            && let Some((old_version, new_version)) =
+                uncommitted_version_bump(package_root, git_root, sub_dir, backend)
+        {
+            eprintln!(
+                "{}: only the version bump from {old_version} to {new_version} in `Cargo.toml` \
+                 is uncommitted, commit it before releasing",
+                "error".red().bold(),
+            );
+            return false;
+        }
+
+        if !status.is_empty() {
+            eprintln!();
+            eprintln!(
+                "{}: {} files in the working directory contain changes \
+                         that were not yet committed into git:",
+                "error".red().bold(),
+                status.len()
+            );
+            eprintln!();
+            for (path, modification_kind) in status {
+                eprintln!("{path}{modification_kind}", path = path.bold());
+            }
+
+            return false;
+        }
+    }
+    true
+}
+
+/// Record a failed check and exit with the given exit code, unless `--keep-going`
+/// or `--no-fail-fast` was passed
+fn check_failed(
+    checks: &mut Vec<(&'static str, CheckResult)>,
+    check: &'static str,
+    exit_code: i32,
+    is_keep_going: bool,
+) {
+    checks.push((check, CheckResult::Failed));
+    if !is_keep_going {
+        std::process::exit(exit_code);
+    }
+}
+
+/// Print what a publish run would do without running any publish step
+fn run_status(
+    registry: &registry::Registry,
+    package_root: &cargo_metadata::camino::Utf8Path,
+    workspace_root: &cargo_metadata::camino::Utf8Path,
+    package_name: &str,
+    package_version: &cargo_metadata::semver::Version,
+    planned_checks: &[(&str, bool)],
+) {
+    println!();
+    println!("Package:  {package_name}");
+    println!("Version:  {package_version}");
+    println!("Path:     {package_root}");
+    println!("Registry: {} ({})", registry.name, registry.index);
+
+    let git_status = match get_git_root(package_root.as_std_path()) {
+        None => "not part of a git repository".normal(),
+        // prints the list of changed files on its own
+        Some(_) if !check_git_is_dirty(package_root, workspace_root) => "uncommitted changes".red(),
+        Some(_) => "clean".green(),
+    };
+    println!("Git:      {git_status}");
+
+    let published = if is_no_network() {
+        "unknown, `--no-network` skips the registry lookup".normal()
+    } else if registry
+        .published_versions(package_name)
+        .contains(package_version)
+    {
+        "this version is already published".red()
+    } else {
+        "this version is not published yet".green()
+    };
+    println!("Status:   {published}");
+
+    println!();
+    println!("Checks:");
+    for (check, enabled) in planned_checks {
+        let state = if *enabled {
+            "will run".green()
+        } else {
+            "skipped".yellow()
+        };
+        println!("  * {check}: {state}");
+    }
+}
+
+/// Print all registries from the cargo configuration with their resolved index
+/// and download urls and mark the one the package would be published to
+fn run_registries(selected: &registry::Registry) {
+    let mut names = registry::configured_registries();
+    if !names.contains(&selected.name) {
+        names.push(selected.name.clone());
+        names.sort();
+    }
+    println!();
+    println!("Registries:");
+    for name in names {
+        let other;
+        let registry = if name == selected.name {
+            selected
+        } else {
+            other = registry::Registry::resolve(
+                Some(name.clone()),
+                None,
+                None,
+                request_options(),
+                None,
+                Vec::new(),
+            );
+            &other
+        };
+        let used = if name == selected.name {
+            format!(" {}", "(used for this publish)".green())
+        } else {
+            String::new()
+        };
+        println!("  * {name}{used}");
+        println!("      Index:    {}", registry.index);
+        let token = if registry.has_token() {
+            "token configured"
+        } else {
+            "no token configured"
+        };
+        let (download, auth) = if is_no_network() {
+            (
+                "unknown, `--no-network` skips fetching the index configuration".normal(),
+                format!("unknown, {token}"),
+            )
+        } else {
+            match registry.download_template() {
+                Ok((template, is_auth_required)) => (
+                    template.normal(),
+                    if is_auth_required {
+                        format!("required, {token}")
+                    } else {
+                        format!("not required for downloads, {token}")
+                    },
+                ),
+                Err(e) => (e.red(), format!("unknown, {token}")),
             }
+        };
+        println!("      Download: {download}");
+        println!("      Auth:     {auth}");
+        if let Some(replacement) = registry.replaced_with() {
+            println!(
+                "      Note:     cargo replaces it with the source `{replacement}`, \
+                 cargo safe-publish still verifies against the registry itself"
+            );
+        }
+    }
+}
+
+/// Print all files of the packaged crate with their permissions and sizes,
+/// sorted by path, similar to `tar tvf`
+fn print_archive_list(crate_file: &[u8]) {
+    let mut archive = tar::Archive::new(GzDecoder::new(crate_file));
+    let mut files = archive
+        .entries()
+        .expect("Could not open the packaged `.crate` archive")
+        .map(|entry| {
+            let entry = entry.expect("Failed to get file entry from tar archive");
+            let path = entry
+                .path()
+                .expect("Invalid path in the packaged crate")
+                .display()
+                .to_string();
+            let mode = entry.header().mode().unwrap_or(0o644);
+            (path, mode, entry.size())
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let total = files.iter().map(|(_, _, size)| size).sum::<u64>();
+    let width = total.to_string().len();
+    println!();
+    for (path, mode, size) in &files {
+        let permissions = (0..9)
+            .rev()
+            .map(|bit| {
+                if mode & (1 << bit) == 0 {
+                    '-'
+                } else {
+                    ['x', 'w', 'r'][bit % 3]
+                }
+            })
+            .collect::<String>();
+        println!("-{permissions} {size:>width$} {path}");
+    }
+    println!();
+    println!("{} files, {total} bytes in total", files.len());
+}
+
+/// A named step of the publish process that is timed by `--benchmark`
+type BenchmarkStep<'a> = (&'static str, Box<dyn Fn() + 'a>);
+
+/// Run all steps except the actual publish multiple times
+/// and print timing statistics for each of them
+fn run_benchmark(steps: &[BenchmarkStep<'_>]) {
+    let runs = flag_value("--benchmark-runs")
+        .map(|runs| {
+            runs.parse::<usize>().unwrap_or_else(|_| {
+                eprintln!(
+                    "{}: invalid value `{runs}` for `--benchmark-runs`, expected a number",
+                    "error".red().bold()
+                );
+                std::process::exit(1);
+            })
+        })
+        .unwrap_or(DEFAULT_BENCHMARK_RUNS)
+        // a single run does not give any meaningful statistics
+        .max(2);
+    eprintln!(
+        "{}: benchmarking repeatedly runs the verification build, \
+         which fills the cargo cache and the target directory",
+        "warning".yellow()
+    );
+
+    let mut timings = steps
+        .iter()
+        .map(|(step, _)| (*step, Vec::with_capacity(runs)))
+        .collect::<Vec<_>>();
+    for run in 1..=runs {
+        print_status(format_args!("Benchmark run {run}/{runs}"));
+        for ((_, run_step), (_, durations)) in steps.iter().zip(&mut timings) {
+            let start = std::time::Instant::now();
+            run_step();
+            durations.push(start.elapsed());
+        }
+    }
+
+    println!();
+    println!("Timings over {runs} runs:");
+    for (step, mut durations) in timings {
+        durations.sort();
+        let mean = durations.iter().sum::<std::time::Duration>() / durations.len() as u32;
+        // nearest rank percentiles
+        let percentile =
+            |p: usize| durations[(durations.len() * p).div_ceil(100).saturating_sub(1)];
+        println!(
+            "  * {step}: mean {mean:.2?}, p50 {:.2?}, p95 {:.2?}",
+            percentile(50),
+            percentile(95)
+        );
+    }
+}
+
+/// Compare two hex encoded checksums
+///
+/// With `--constant-time-compare` the comparison does not exit early on the first difference
+fn checksums_match(expected: &str, actual: &str) -> bool {
+    if !std::env::args().any(|c| c == "--constant-time-compare") {
+        return expected.eq_ignore_ascii_case(actual);
+    }
+    // the length of a checksum is no secret
+    if expected.len() != actual.len() {
+        return false;
+    }
+    let difference = expected
+        .bytes()
+        .zip(actual.bytes())
+        .fold(0, |difference, (e, a)| {
+            difference | (e.to_ascii_lowercase() ^ a.to_ascii_lowercase())
+        });
+    std::hint::black_box(difference) == 0
+}
+
+fn is_diff_exit_zero() -> bool {
+    std::env::args().any(|c| c == "--diff-exit-zero" || c == "--treat-diff-as-warning")
+}
+
+/// Content differences are warnings with `--diff-exit-zero`
+fn difference_label() -> colored::ColoredString {
+    if is_diff_exit_zero() {
+        "warning".yellow()
+    } else {
+        "error".red().bold()
+    }
+}
+
+/// Exit after a content difference was reported, unless `--diff-exit-zero` was passed
+fn exit_on_difference() {
+    if !is_diff_exit_zero() {
+        std::process::exit(1);
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Verify an already published crate version without publishing anything
+fn run_verify(
+    registry: &registry::Registry,
+    package: &cargo_metadata::Package,
+    output_format: OutputFormat,
+    config: &config::Config,
+) {
+    let package_root = package.manifest_path.parent().unwrap();
+    let package_name = package.name.as_str();
+    let package_version = &package.version;
+    let repo;
+    let is_compare_committed = std::env::args().any(|c| c == "--compare-committed");
+    let git_ref = match flag_value("--against-git-ref") {
+        Some(_) if is_compare_committed => {
+            eprintln!(
+                "{}: `--compare-committed` and `--against-git-ref` cannot be used together",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        }
+        // compare against the committed state, independent of local uncommitted changes
+        None if is_compare_committed => Some("HEAD".to_owned()),
+        git_ref => git_ref,
+    };
+    let local_source = if let Some(git_ref) = git_ref {
+        let Some(git_root) = get_git_root(package_root.as_std_path()) else {
+            eprintln!(
+                "{}: comparing against git requires `{package_root}` to be part of a git repository",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        };
+        repo = gix::open(git_root).expect("Could not open git repo");
+        let tree = repo
+            .rev_parse_single(git_ref.as_str())
+            .ok()
+            .and_then(|id| id.object().ok()?.peel_to_tree().ok())
+            .unwrap_or_else(|| {
+                eprintln!(
+                    "{}: could not resolve the git ref `{git_ref}` to a tree",
+                    "error".red().bold()
+                );
+                std::process::exit(1);
+            });
+        let package_prefix = package_root
+            .as_std_path()
+            .strip_prefix(git_root)
+            .expect("The package_root path is a child path or equivalent to the git root path")
+            .to_owned();
+        LocalSource::GitTree {
+            git_ref,
+            tree,
+            package_prefix,
+        }
+    } else {
+        LocalSource::WorkingTree
+    };
+    let crate_file = flag_value("--crate-file").map(PathBuf::from);
+    if is_no_network() {
+        if crate_file.is_none() {
+            eprintln!(
+                "{}: verifying the published crate requires network access, \
+                 pass a local crate file via `--crate-file` with `--no-network`",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        }
+        report_no_network_skips(&[(
+            "Manifest dependency check",
+            std::env::args().any(|c| c == "--check-manifest-dependencies"),
+        )]);
+    }
+    let crate_description = match &crate_file {
+        Some(crate_file) => format!("the crate file `{}`", crate_file.display()),
+        None => format!("the published crate `{package_name}` ({package_version})"),
+    };
+    print_status(format_args!(
+        "Verify {crate_description} against {}",
+        local_source.describe(package_root)
+    ));
 
+    let report = if let Some(crate_file) = &crate_file {
+        let expected_file_name = format!("{package_name}-{package_version}.crate");
+        if crate_file.file_name() != Some(expected_file_name.as_ref()) {
+            eprintln!(
+                "{}: expected the crate file to be named `{expected_file_name}`, got `{}`",
+                "error".red().bold(),
+                crate_file.display()
+            );
+            std::process::exit(1);
+        }
+        let file = std::fs::File::open(crate_file).unwrap_or_else(|e| {
+            eprintln!(
+                "{}: failed to open the crate file `{}`: {e}",
+                "error".red().bold(),
+                crate_file.display()
+            );
             std::process::exit(1);
+        });
+        verify_content_matches(
+            std::io::BufReader::new(file),
+            &local_source,
+            package,
+            None,
+            &config.verification.remap,
+        )
+    } else {
+        verify_content_matches(
+            registry.download(package_name, package_version),
+            &local_source,
+            package,
+            None,
+            &config.verification.remap,
+        )
+    }
+    .unwrap_or_else(|exit_code| std::process::exit(exit_code));
+    write_report(&report, output_format);
+    // a local crate file is not necessarily published yet
+    if crate_file.is_none()
+        && let Some(checksum) = registry.published_checksum(package_name, package_version)
+        && !checksums_match(&checksum, &report.checksum)
+    {
+        eprintln!(
+            "{}: the checksum of the downloaded crate ({}) does not match the checksum recorded in the registry index ({checksum})",
+            "error".red().bold(),
+            report.checksum
+        );
+        std::process::exit(1);
+    }
+    if std::env::args().any(|c| c == "--print-manifest") {
+        match &report.manifest {
+            Some(manifest) => {
+                print_status("");
+                print_status(format_args!(
+                    "The `Cargo.toml` of {crate_description} as rewritten by cargo:"
+                ));
+                print_status("");
+                print_status(manifest.trim_end());
+            }
+            None => eprintln!(
+                "{}: {crate_description} does not contain a `Cargo.toml`",
+                "warning".yellow()
+            ),
         }
     }
-}
+    if std::env::args().any(|c| c == "--check-manifest-dependencies")
+        && !is_no_network()
+        && let Some(manifest) = &report.manifest
+        && !checks::check_manifest_dependencies(registry, manifest)
+    {
+        eprintln!();
+        eprintln!(
+            "{}: {} depends on versions that cannot be resolved from the registry `{}`",
+            "error".red().bold(),
+            capitalize(&crate_description),
+            registry.name
+        );
+        std::process::exit(1);
+    }
+    let is_platform_files_complete =
+        checks::check_platform_files(package_root, &config.platform_files, &report);
+    if report.everything_matched() && is_platform_files_complete {
+        print_status("");
+        print_status(format_args!(
+            "{} matches {}",
+            capitalize(&crate_description),
+            local_source.describe(package_root)
+        ));
+    } else if !report.everything_matched() {
+        eprintln!();
+        eprintln!(
+            "{}: Found a difference between {crate_description} and {}",
+            difference_label(),
+            local_source.describe(package_root)
+        );
+        exit_on_difference();
+    } else {
+        eprintln!();
+        eprintln!(
+            "{}: {} misses platform specific files",
+            "error".red().bold(),
+            capitalize(&crate_description),
+        );
+        std::process::exit(1);
+    }
+}
+
+fn main() {
+    configure_color();
+    // report an invalid arguments file before running any check
+    publish_args_file_arguments();
+    let is_no_network = is_no_network();
+    // publishing needs network access, so `--no-network` always implies a dry run
+    let is_dry_run = std::env::args().any(|c| c == "--dry-run") || is_no_network;
+    let is_no_verify = std::env::args().any(|c| c == "--no-verify");
+    let is_no_publish = std::env::args().any(|c| c == "--no-publish");
+    let is_help = std::env::args().any(|c| c == "--help" || c == "-h");
+    let is_allow_dirty = std::env::args().any(|c| c == "--allow-dirty");
+    let is_version = std::env::args().any(|c| c == "--version" || c == "-V");
+    // the last of these flags wins, which allows to override a `--no-fail-fast` set in an alias
+    let is_keep_going = std::env::args()
+        .rev()
+        .find_map(|c| match c.as_str() {
+            "--keep-going" | "--no-fail-fast" => Some(true),
+            "--fail-fast" => Some(false),
+            _ => None,
+        })
+        .unwrap_or(false);
+    let is_ignore_rustflags = std::env::args().any(|c| c == "--ignore-rustflags");
+    let is_verify_msrv = std::env::args().any(|c| c == "--verify-msrv");
+    let is_miri_check = std::env::args().any(|c| c == "--miri-check");
+    let is_check_feature_powerset = std::env::args().any(|c| c == "--check-feature-powerset");
+    let is_verify_examples = std::env::args().any(|c| c == "--verify-examples");
+    let is_allow_nonmonotonic = std::env::args().any(|c| c == "--allow-nonmonotonic");
+    let is_workspace_version_sync = std::env::args().any(|c| c == "--workspace-version-sync");
+    let is_require_no_path_deps = std::env::args().any(|c| c == "--require-no-path-deps");
+    let is_deny_wildcard_deps = std::env::args().any(|c| c == "--deny-wildcard-deps");
+    let is_strip_dev_deps = std::env::args().any(|c| c == "--strip-dev-deps");
+    let is_allow_panic_abort = std::env::args().any(|c| c == "--allow-panic-abort");
+    let is_changelog_check = std::env::args().any(|c| c == "--changelog-check");
+    let is_benchmark = std::env::args().any(|c| c == "--benchmark");
+    let is_archive_list = std::env::args().any(|c| c == "--archive-list");
+    let is_strict = std::env::args().any(|c| c == "--strict");
+    let change_threshold = change_threshold();
+    let allowed_cargo_warnings = std::env::args()
+        .any(|c| c == "--deny-cargo-warning")
+        .then(|| {
+            flag_values("--allow-cargo-warning")
+                .into_iter()
+                .map(|pattern| {
+                    regex::Regex::new(&pattern).unwrap_or_else(|e| {
+                        eprintln!(
+                            "{}: invalid pattern `{pattern}` for `--allow-cargo-warning`: {e}",
+                            "error".red().bold()
+                        );
+                        std::process::exit(1);
+                    })
+                })
+                .collect::<Vec<_>>()
+        });
+    let is_warn_debug_assertions = std::env::args().any(|c| c == "--warn-debug-assertions");
+    let verify_targets = flag_values("--verify-target");
+    let output_format = output_format();
+    let verification_wait = verification_wait();
+    let report_file = report_file();
+    let attestation_file = flag_value("--attestation").map(PathBuf::from);
+    let release_notes = release_notes();
+    let subcommand = subcommand();
+    if is_version {
+        println!("cargo safe-publish {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+    if is_no_publish && is_no_verify {
+        eprintln!(
+            "{}: `--no-publish` only runs the checks and the verification build, \
+             which `--no-verify` skips",
+            "error".red().bold()
+        );
+        std::process::exit(1);
+    }
+    if is_no_publish
+        && (report_file.is_some() || attestation_file.is_some() || release_notes.is_some())
+    {
+        eprintln!(
+            "{}: `--report-file`, `--attestation` and `--release-notes` have no effect with `--no-publish`",
+            "warning".yellow()
+        );
+    }
+
+    check_cargo_available();
+
+    let package_filter = package_filter();
+    if package_filter.is_some()
+        && (package_flag().is_some() || std::env::args().any(|c| c == "--workspace"))
+    {
+        eprintln!(
+            "{}: `--package-filter` cannot be combined with `--package` or `--workspace`",
+            "error".red().bold()
+        );
+        std::process::exit(1);
+    }
 
-fn main() {
-    let is_dry_run = std::env::args().any(|c| c == "--dry-run");
-    let is_no_verify = std::env::args().any(|c| c == "--no-verify");
-    let is_help = std::env::args().any(|c| c == "--help" || c == "-h");
-    let is_allow_dirty = std::env::args().any(|c| c == "--allow-dirty");
-    let is_version = std::env::args().any(|c| c == "--version" || c == "-v");
-    if is_version {
-        println!("cargo safe-publish {}", env!("CARGO_PKG_VERSION"));
-        return;
+    // the version bump changes the metadata, so this needs to happen before reading it
+    // with `--package-filter` the version of each package is bumped in its own run
+    if let Some(level) = flag_value("--release")
+        && subcommand.is_none()
+        && package_filter.is_none()
+        && !is_help
+        && let Err(exit_code) = run_release(&level)
+    {
+        std::process::exit(exit_code);
     }
 
     let manifest_path = manifest_path();
@@ -437,10 +3549,22 @@ fn main() {
     if let Some(manifest_path) = &manifest_path {
         other_options.extend_from_slice(&["--manifest-path".to_owned(), manifest_path.to_owned()]);
     }
+    // `cargo metadata` has no `--target-dir` flag, but reports the configured target directory
+    if let Some(target_dir) = target_dir_flag() {
+        let target_dir = toml::Value::String(target_dir.display().to_string());
+        other_options.extend_from_slice(&[
+            "--config".to_owned(),
+            format!("build.target-dir={target_dir}"),
+        ]);
+    }
     metadata_command.other_options(other_options);
     let metadata = metadata_command
         .exec()
         .expect("Failed to get project metadata");
+    if let Some(package_filter) = &package_filter {
+        run_filtered_packages(&metadata, package_filter);
+        return;
+    }
     let target_directory = &metadata.target_directory;
     let package_flag = package_flag();
     let package_to_publish = if let Some(package_flag) = package_flag {
@@ -467,45 +3591,959 @@ fn main() {
     let package_root = package_to_publish.manifest_path.parent().unwrap();
     let package_version = &package_to_publish.version;
     let package_name = &package_to_publish.name;
-    println!(
+    print_status(format_args!(
         "Run cargo safe-publish for the crate `{package_name} {package_version} ({package_root})`",
+    ));
+    let tool_versions = tool_versions();
+    print_status(format_args!(
+        "Using {}, {} and cargo-safe-publish {APP_VERSION}",
+        tool_versions
+            .cargo
+            .as_deref()
+            .unwrap_or("an unknown cargo version"),
+        tool_versions
+            .rustc
+            .as_deref()
+            .unwrap_or("an unknown rustc version"),
+    ));
+
+    if let Some(tag) = flag_value("--since-tag")
+        && subcommand.is_none()
+    {
+        let changed = packages_changed_since(&metadata, package_root.as_std_path(), &tag);
+        print_status(format_args!(
+            "Packages changed since `{tag}`: {}",
+            if changed.is_empty() {
+                "none".to_owned()
+            } else {
+                changed
+                    .iter()
+                    .map(|p| format!("`{}`", p.name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+        ));
+        if !changed.iter().any(|p| p.id == package_to_publish.id) {
+            print_status(format_args!(
+                "`{package_name}` did not change since `{tag}`, nothing to publish"
+            ));
+            return;
+        }
+    }
+
+    let registry = registry::Registry::resolve(
+        flag_value("--registry"),
+        flag_value("--token"),
+        package_to_publish.publish.as_deref(),
+        request_options(),
+        flag_value("--index-path").map(PathBuf::from),
+        flag_values("--download-mirror"),
     );
+    let is_refresh_index = std::env::args().any(|c| c == "--refresh-index");
+    if is_refresh_index && !is_no_network {
+        registry.refresh_index(package_name);
+    }
+
+    let config = config::Config::load(package_root.as_std_path());
+    let commit_format = commit_format_pattern(&config, package_root);
+    let is_pre_publish_hooks =
+        hooks::hook_directory(package_root.as_std_path(), hooks::Stage::PrePublish).is_some();
+    let is_require_repository = std::env::args().any(|c| c == "--require-repository")
+        || config.require_repository.unwrap_or(false);
+
+    // drives both the `status` subcommand and the checks of the actual run,
+    // so that both always agree on what runs
+    let is_workspace = std::env::args().any(|c| c == "--workspace");
+    let planned_checks = [
+        ("Git dirty check", !is_allow_dirty),
+        ("Commit message format", commit_format.is_some()),
+        ("Panic strategy", !is_allow_panic_abort),
+        ("Feature consistency", true),
+        ("Build script reruns", true),
+        ("Workspace dependency versions", is_workspace),
+        ("docs.rs metadata", true),
+        ("Changelog entry", is_changelog_check),
+        ("Repository url", is_require_repository),
+        ("Path dependencies", is_require_no_path_deps),
+        ("Dependency versions", true),
+        ("Dev-dependency features", is_strip_dev_deps),
+        ("Workspace version sync", is_workspace_version_sync),
+        ("Name collision check", !is_no_network),
+        ("Version increase", !is_allow_nonmonotonic && !is_no_network),
+        ("RUSTFLAGS check", !is_no_verify && !is_ignore_rustflags),
+        ("Verification build", !is_no_verify),
+        ("Blocked files", !is_no_verify),
+        ("Packaged directories", !is_no_verify),
+        ("Readme", !is_no_verify),
+        (
+            "Change threshold",
+            !is_no_verify && change_threshold.is_some() && !is_no_network,
+        ),
+        ("MSRV build", is_verify_msrv),
+        ("Cross-compilation check", !verify_targets.is_empty()),
+        ("Miri check", is_miri_check),
+        ("Feature powerset", is_check_feature_powerset),
+        ("Examples", is_verify_examples),
+        ("Pre-publish hooks", is_pre_publish_hooks),
+        (
+            "Content verification",
+            verification_wait.is_some() && !(is_dry_run && is_no_verify) && !is_no_publish,
+        ),
+        (
+            "Platform files",
+            !is_dry_run
+                && !is_no_publish
+                && verification_wait.is_some()
+                && !config.platform_files.is_empty(),
+        ),
+    ];
+    let is_planned = |check: &str| {
+        planned_checks
+            .iter()
+            .find(|(planned, _)| *planned == check)
+            .unwrap_or_else(|| panic!("the check `{check}` is missing from the planned checks"))
+            .1
+    };
+
+    if subcommand.as_deref() == Some("verify") {
+        run_verify(&registry, package_to_publish, output_format, &config);
+        return;
+    }
+    if subcommand.as_deref() == Some("registries") {
+        run_registries(&registry);
+        return;
+    }
+    if subcommand.as_deref() == Some("status") {
+        run_status(
+            &registry,
+            package_root,
+            &metadata.workspace_root,
+            package_name.as_str(),
+            package_version,
+            &planned_checks,
+        );
+        return;
+    }
+
+    if is_benchmark {
+        let steps: [BenchmarkStep<'_>; 3] = [
+            (
+                "Git dirty check",
+                Box::new(|| {
+                    check_git_is_dirty(package_root, &metadata.workspace_root);
+                }),
+            ),
+            (
+                "Version increase",
+                Box::new(|| {
+                    registry.published_versions(package_name);
+                }),
+            ),
+            (
+                "Verification build",
+                Box::new(|| {
+                    if let Err(exit_code) = run_verification_build(
+                        target_directory.as_std_path(),
+                        package_name.as_str(),
+                        package_version,
+                        None,
+                    ) {
+                        std::process::exit(exit_code);
+                    }
+                }),
+            ),
+        ];
+        let steps = steps
+            .into_iter()
+            .filter(|(step, _)| is_planned(step))
+            .collect::<Vec<_>>();
+        run_benchmark(&steps);
+        return;
+    }
+
+    if is_archive_list || std::env::args().any(|c| c == "--print-file-list") {
+        match run_verification_build(
+            target_directory.as_std_path(),
+            package_name.as_str(),
+            package_version,
+            None,
+        ) {
+            Ok(verification_build) if is_archive_list => {
+                print_archive_list(&verification_build.crate_file)
+            }
+            Ok(verification_build) => {
+                // cargo uses `/` as separator on all platforms
+                for file in checks::packaged_files(&verification_build.crate_file) {
+                    let components = file
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>();
+                    println!("{}", components.join("/"));
+                }
+            }
+            Err(exit_code) => std::process::exit(exit_code),
+        }
+        return;
+    }
+
+    if is_no_network {
+        let is_publish = !is_help && !is_no_publish && !std::env::args().any(|c| c == "--dry-run");
+        report_no_network_skips(&[
+            ("Index refresh", is_refresh_index),
+            ("Name collision check", true),
+            ("Version increase", !is_allow_nonmonotonic),
+            (
+                "Change threshold",
+                !is_no_verify && change_threshold.is_some(),
+            ),
+            ("Publish", is_publish),
+            ("Content verification of the published crate", is_publish),
+            ("Webhook notifications", is_publish),
+        ]);
+    }
+
+    let mut timer = Timer::new(is_verbose());
+    let mut checks = Vec::new();
+    warn_include_and_exclude(package_root, &metadata.workspace_root);
+    if is_planned("Git dirty check") {
+        if check_git_is_dirty(package_root, &metadata.workspace_root) {
+            checks.push(("Git dirty check", CheckResult::Passed));
+        } else {
+            check_failed(&mut checks, "Git dirty check", 1, is_keep_going);
+        }
+        timer.lap("Git dirty check");
+    } else {
+        checks.push(("Git dirty check", CheckResult::Skipped));
+    }
+
+    if let Some(commit_format) = commit_format
+        .as_ref()
+        .filter(|_| is_planned("Commit message format"))
+    {
+        if check_commit_format(package_root, commit_format) {
+            checks.push(("Commit message format", CheckResult::Passed));
+        } else {
+            check_failed(&mut checks, "Commit message format", 1, is_keep_going);
+        }
+    }
+
+    // these checks only warn and never fail the run
+    if is_planned("Panic strategy") {
+        checks::check_panic_abort(package_to_publish);
+        checks.push(("Panic strategy", CheckResult::Passed));
+    }
+    if is_planned("Feature consistency") {
+        checks::check_feature_consistency(package_to_publish);
+        checks.push(("Feature consistency", CheckResult::Passed));
+    }
+    if is_planned("Build script reruns") {
+        checks::check_build_script_rerun(package_to_publish);
+        checks.push(("Build script reruns", CheckResult::Passed));
+    }
+
+    if std::env::args().any(|c| c == "--detect-unpublished-changes") {
+        report_unpublished_changes(&metadata, package_to_publish);
+    }
+
+    // `--workspace` is passed on to cargo, which then publishes all members
+    if is_planned("Workspace dependency versions") {
+        checks::check_workspace_dependency_versions(&metadata);
+        checks.push(("Workspace dependency versions", CheckResult::Passed));
+    }
+
+    if is_warn_debug_assertions || is_verbose() {
+        checks::report_debug_assertions(package_root, is_warn_debug_assertions);
+    }
+
+    if std::env::args().any(|c| c == "--check-test-exports") {
+        checks::report_test_exports(package_to_publish);
+    }
+
+    if is_planned("docs.rs metadata") {
+        if checks::check_docs_rs_metadata(package_to_publish) {
+            checks.push(("docs.rs metadata", CheckResult::Passed));
+        } else {
+            check_failed(&mut checks, "docs.rs metadata", 1, is_keep_going);
+        }
+    }
+
+    if is_planned("Changelog entry") {
+        let changelog = package_root.as_std_path().join(
+            config
+                .changelog
+                .as_deref()
+                .unwrap_or("CHANGELOG.md".as_ref()),
+        );
+        if checks::check_changelog(&changelog, package_version) {
+            checks.push(("Changelog entry", CheckResult::Passed));
+        } else {
+            check_failed(&mut checks, "Changelog entry", 1, is_keep_going);
+        }
+    }
+
+    if is_planned("Repository url") {
+        if checks::check_repository(package_to_publish) {
+            checks.push(("Repository url", CheckResult::Passed));
+        } else {
+            check_failed(&mut checks, "Repository url", 1, is_keep_going);
+        }
+    }
+
+    if is_planned("Path dependencies") {
+        if checks::check_no_path_dependencies(package_root) {
+            checks.push(("Path dependencies", CheckResult::Passed));
+        } else {
+            check_failed(&mut checks, "Path dependencies", 1, is_keep_going);
+        }
+    }
 
-    if !is_allow_dirty {
-        check_git_is_dirty(package_root);
+    if is_planned("Dependency versions") {
+        if checks::check_dependency_requirements(package_to_publish, is_deny_wildcard_deps) {
+            checks.push(("Dependency versions", CheckResult::Passed));
+        } else {
+            check_failed(&mut checks, "Dependency versions", 1, is_keep_going);
+        }
+    }
+
+    if is_planned("Dev-dependency features") {
+        match resolved_metadata(package_to_publish, &metadata.workspace_root) {
+            Ok(resolved_metadata)
+                if checks::check_dev_dependency_features(
+                    &resolved_metadata,
+                    package_to_publish,
+                ) =>
+            {
+                checks.push(("Dev-dependency features", CheckResult::Passed));
+            }
+            Ok(_) => check_failed(&mut checks, "Dev-dependency features", 1, is_keep_going),
+            Err(exit_code) => check_failed(
+                &mut checks,
+                "Dev-dependency features",
+                exit_code,
+                is_keep_going,
+            ),
+        }
+    }
+
+    if is_planned("Workspace version sync") {
+        if checks::check_workspace_version_sync(&metadata, package_to_publish) {
+            checks.push(("Workspace version sync", CheckResult::Passed));
+        } else {
+            check_failed(&mut checks, "Workspace version sync", 1, is_keep_going);
+        }
+    }
+
+    if is_planned("Name collision check") {
+        checks::check_name_collision(
+            &registry,
+            package_name.as_str(),
+            std::env::args().any(|c| c == "--check-name-collision"),
+        );
+        checks.push(("Name collision check", CheckResult::Passed));
+    }
+
+    if is_planned("Version increase") {
+        if checks::check_version_increases(&registry, package_name.as_str(), package_version) {
+            checks.push(("Version increase", CheckResult::Passed));
+        } else {
+            check_failed(&mut checks, "Version increase", 1, is_keep_going);
+        }
+    } else if !is_allow_nonmonotonic {
+        checks.push(("Version increase", CheckResult::Skipped));
     }
 
-    let lock_file_content = if !is_no_verify {
-        Some(run_verification_build(
+    let verification_build = if is_planned("Verification build") {
+        if is_planned("RUSTFLAGS check") {
+            check_rustflags();
+            checks.push(("RUSTFLAGS check", CheckResult::Passed));
+        }
+        let verification_build = match run_verification_build(
             target_directory.as_std_path(),
             package_name.as_str(),
             package_version,
-        ))
+            allowed_cargo_warnings.as_deref(),
+        ) {
+            Ok(verification_build) => {
+                checks.push(("Verification build", CheckResult::Passed));
+                Some(verification_build)
+            }
+            Err(exit_code) => {
+                check_failed(&mut checks, "Verification build", exit_code, is_keep_going);
+                None
+            }
+        };
+        timer.lap("Verification build");
+        if let Some(verification_build) = &verification_build {
+            if is_planned("Blocked files") {
+                if checks::check_blocked_files(
+                    package_root,
+                    &verification_build.crate_file,
+                    &config.blocked_files,
+                    is_strict,
+                ) || !is_strict
+                {
+                    checks.push(("Blocked files", CheckResult::Passed));
+                } else {
+                    check_failed(&mut checks, "Blocked files", 1, is_keep_going);
+                }
+            }
+            if is_planned("Packaged directories") {
+                if checks::check_packaged_directories(&verification_build.crate_file, &config) {
+                    checks.push(("Packaged directories", CheckResult::Passed));
+                } else {
+                    check_failed(&mut checks, "Packaged directories", 1, is_keep_going);
+                }
+            }
+            if is_planned("Readme") {
+                let is_readme_disabled =
+                    read_package_manifest(package_root, &metadata.workspace_root)
+                        .package
+                        .is_some_and(|p| *p.readme() == cargo_toml::OptionalFile::Flag(false));
+                if checks::check_readme(
+                    package_root,
+                    is_readme_disabled,
+                    package_to_publish.readme.as_deref(),
+                    &verification_build.crate_file,
+                ) {
+                    checks.push(("Readme", CheckResult::Passed));
+                } else {
+                    check_failed(&mut checks, "Readme", 1, is_keep_going);
+                }
+            }
+            if is_planned("Change threshold")
+                && let Some(change_threshold) = change_threshold
+            {
+                if checks::check_change_magnitude(
+                    &registry,
+                    package_name.as_str(),
+                    package_version,
+                    &verification_build.crate_file,
+                    change_threshold,
+                    is_strict,
+                ) || (!is_strict && confirm_change_threshold())
+                {
+                    checks.push(("Change threshold", CheckResult::Passed));
+                } else {
+                    check_failed(&mut checks, "Change threshold", 1, is_keep_going);
+                }
+            } else if change_threshold.is_some() {
+                checks.push(("Change threshold", CheckResult::Skipped));
+            }
+        }
+        verification_build
     } else {
+        checks.push(("Verification build", CheckResult::Skipped));
         None
     };
 
-    if !is_dry_run && !is_help {
-        run_publish();
+    if is_planned("MSRV build") {
+        match run_msrv_build(package_to_publish) {
+            Ok(()) => checks.push(("MSRV build", CheckResult::Passed)),
+            Err(exit_code) => check_failed(&mut checks, "MSRV build", exit_code, is_keep_going),
+        }
+        timer.lap("MSRV build");
+    }
 
-        let everything_matched = verify_content_matches(
-            package_root,
-            package_version,
+    if is_planned("Cross-compilation check") {
+        match run_target_checks(package_to_publish, &verify_targets) {
+            Ok(()) => checks.push(("Cross-compilation check", CheckResult::Passed)),
+            Err(exit_code) => check_failed(
+                &mut checks,
+                "Cross-compilation check",
+                exit_code,
+                is_keep_going,
+            ),
+        }
+        timer.lap("Cross-compilation check");
+    }
+
+    if is_planned("Miri check") {
+        match run_miri_check(package_to_publish) {
+            Ok(result) => checks.push(("Miri check", result)),
+            Err(exit_code) => check_failed(&mut checks, "Miri check", exit_code, is_keep_going),
+        }
+        timer.lap("Miri check");
+    }
+
+    if is_planned("Feature powerset") {
+        match run_feature_powerset_check(package_to_publish) {
+            Ok(result) => checks.push(("Feature powerset", result)),
+            Err(exit_code) => {
+                check_failed(&mut checks, "Feature powerset", exit_code, is_keep_going)
+            }
+        }
+        timer.lap("Feature powerset");
+    }
+
+    if is_planned("Examples") {
+        match run_example_checks(package_to_publish) {
+            Ok(()) => checks.push(("Examples", CheckResult::Passed)),
+            Err(exit_code) => check_failed(&mut checks, "Examples", exit_code, is_keep_going),
+        }
+        timer.lap("Examples");
+    }
+
+    if is_planned("Pre-publish hooks") {
+        match hooks::run_hooks(
+            package_root.as_std_path(),
+            hooks::Stage::PrePublish,
             package_name.as_str(),
-            lock_file_content,
+            package_version,
+            &registry.name,
+        ) {
+            Ok(()) => checks.push(("Pre-publish hooks", CheckResult::Passed)),
+            Err(exit_code) => {
+                check_failed(&mut checks, "Pre-publish hooks", exit_code, is_keep_going)
+            }
+        }
+        timer.lap("Pre-publish hooks");
+    }
+
+    // only reachable with `--keep-going`/`--no-fail-fast` if any check failed
+    let failed_checks = checks
+        .iter()
+        .filter(|(_, result)| *result == CheckResult::Failed)
+        .map(|(check, _)| *check)
+        .collect::<Vec<_>>();
+    if !failed_checks.is_empty() {
+        eprintln!();
+        eprintln!(
+            "{}: {} checks failed, not publishing `{package_name}` ({package_version}):",
+            "error".red().bold(),
+            failed_checks.len()
         );
-        if everything_matched {
-            println!();
-            println!("Successfully published and verified `{package_name}` ({package_version})");
+        for check in failed_checks {
+            eprintln!("  * {check}");
+        }
+        std::process::exit(1);
+    }
+
+    if is_no_publish && !is_help {
+        print_status("");
+        print_status(format_args!(
+            "All checks passed, `{package_name}` ({package_version}) is ready to be published"
+        ));
+        return;
+    }
+
+    let (lock_file_content, crate_file) = match verification_build {
+        Some(VerificationBuild {
+            lock_file_content,
+            crate_file,
+        }) => (Some(lock_file_content), Some(crate_file)),
+        None => (None, None),
+    };
+
+    // without publishing anything we can still compare
+    // the crate packaged by the verification build
+    if is_dry_run
+        && !is_help
+        && is_planned("Content verification")
+        && let Some(crate_file) = crate_file
+    {
+        let report = verify_content_matches(
+            crate_file.as_slice(),
+            &LocalSource::WorkingTree,
+            package_to_publish,
+            lock_file_content,
+            &config.verification.remap,
+        )
+        .unwrap_or_else(|exit_code| std::process::exit(exit_code));
+        write_report(&report, output_format);
+        timer.lap("Content verification");
+        if report.everything_matched() {
+            print_status("");
+            print_status(format_args!(
+                "Dry run: the packaged crate `{package_name}` ({package_version}) matches the local version"
+            ));
+        } else {
+            eprintln!();
+            eprintln!(
+                "{}: Dry run: found a difference between the packaged crate `{package_name}` ({package_version}) \
+                 and the local version",
+                difference_label()
+            );
+            exit_on_difference();
+        }
+        return;
+    }
+
+    if !is_dry_run && !is_help {
+        run_publish(package_name.as_str(), &registry);
+        let published_at = gix::date::Time::now_utc();
+        timer.lap("Publish");
+
+        let report = if let Some(verification_wait) = verification_wait {
+            // crates.io might not have processed the upload yet
+            // so give it some time before downloading the crate again
+            if !verification_wait.is_zero() {
+                print_status(format_args!(
+                    "Wait {}s before verifying the published content",
+                    verification_wait.as_secs()
+                ));
+                std::thread::sleep(verification_wait);
+                timer.lap("Waiting for the registry");
+            }
+
+            // stream the archive instead of loading it into memory as a whole
+            // to keep the memory usage low for large crates
+            let report = verify_content_matches(
+                registry.download(package_name, package_version),
+                &LocalSource::WorkingTree,
+                package_to_publish,
+                lock_file_content,
+                &config.verification.remap,
+            )
+            .unwrap_or_else(|exit_code| std::process::exit(exit_code));
+            write_report(&report, output_format);
+            timer.lap("Content verification");
+            checks.push((
+                "Content verification",
+                if report.everything_matched() {
+                    CheckResult::Passed
+                } else {
+                    CheckResult::Failed
+                },
+            ));
+            if is_planned("Platform files") {
+                checks.push((
+                    "Platform files",
+                    if checks::check_platform_files(package_root, &config.platform_files, &report) {
+                        CheckResult::Passed
+                    } else {
+                        CheckResult::Failed
+                    },
+                ));
+            }
+            Some(report)
         } else {
+            checks.push(("Content verification", CheckResult::Skipped));
+            None
+        };
+
+        let is_platform_files_complete = !checks.contains(&("Platform files", CheckResult::Failed));
+        let publish_report = PublishReport {
+            package_name: package_name.as_str(),
+            package_version,
+            registry: &registry.name,
+            repository: package_to_publish.repository.as_deref(),
+            published_at,
+            finished_at: gix::date::Time::now_utc(),
+            git_commit: git_head_commit(package_root.as_std_path()),
+            checks,
+            verification: report.as_ref(),
+            tool_versions: &tool_versions,
+            crate_url: registry.crate_url(package_name.as_str(), package_version),
+            release_notes: release_notes.as_deref(),
+        };
+        if let Some(report_file) = report_file
+            && let Err(e) = std::fs::write(&report_file, publish_report.to_markdown())
+        {
+            eprintln!(
+                "{}: failed to write the publish report to `{}`: {e}",
+                "warning".yellow(),
+                report_file.display()
+            );
+        }
+        if let Some(attestation_file) = attestation_file {
+            match publish_report.to_attestation() {
+                Some(attestation) => {
+                    if let Err(e) = std::fs::write(&attestation_file, attestation) {
+                        eprintln!(
+                            "{}: failed to write the attestation to `{}`: {e}",
+                            "warning".yellow(),
+                            attestation_file.display()
+                        );
+                    }
+                }
+                None => eprintln!(
+                    "{}: not writing an attestation as the checksum of the published crate is unknown \
+                     without the content verification",
+                    "warning".yellow()
+                ),
+            }
+        }
+
+        let after_publish = || {
+            // the crate is already published, so failed hooks are only reported
+            if let Err(exit_code) = hooks::run_hooks(
+                package_root.as_std_path(),
+                hooks::Stage::PostPublish,
+                package_name.as_str(),
+                package_version,
+                &registry.name,
+            ) {
+                eprintln!(
+                    "{}: a post-publish hook failed with the exit code {exit_code}",
+                    "warning".yellow()
+                );
+            }
+            notification::send_webhook(
+                &config.notifications,
+                package_name.as_str(),
+                package_version,
+                &registry.name,
+                publish_report.crate_url.as_deref(),
+                release_notes.as_deref(),
+            )
+        };
+        let Some(report) = report else {
+            print_status("");
+            print_status(format_args!(
+                "Published `{package_name}` ({package_version}), skipped the content verification due to `--no-wait`"
+            ));
+            after_publish();
+            return;
+        };
+        if report.everything_matched() && is_platform_files_complete {
+            print_status("");
+            print_status(format_args!(
+                "Successfully published and verified `{package_name}` ({package_version})"
+            ));
+            after_publish();
+        } else if !report.everything_matched() {
             eprintln!();
             eprintln!(
                 "{}: Found a difference between the uploaded and the local version. \
                  Double check if thats desired, otherwise please yank \
                  version {package_version} of `{package_name}`",
+                difference_label()
+            );
+            exit_on_difference();
+        } else {
+            eprintln!();
+            eprintln!(
+                "{}: The published crate misses platform specific files. \
+                 Double check if thats desired, otherwise please yank \
+                 version {package_version} of `{package_name}`",
                 "error".red().bold()
             );
             std::process::exit(1);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_diff_without_colors() {
+        colored::control::set_override(is_color_enabled(Some("never")));
+        let diff = render_diff("fn main() {}\n", "fn main() {\n    println!(\"hi\");\n}\n");
+        assert!(!diff.contains("\x1b["), "{diff:?}");
+        assert!(diff.contains("-fn main() {}"));
+        assert!(diff.contains("+fn main() {"));
+    }
+
+    #[test]
+    fn large_binary_file_differs() {
+        let size = MAX_TEXT_DIFF_SIZE as usize + 1;
+        let local = vec![0u8; size];
+        let mut uploaded = vec![0u8; size];
+        uploaded[size - 1] = 1;
+        let options = CompareOptions {
+            mode: CompareMode::Exact,
+            is_summary_only: true,
+        };
+        let status = compare_content(
+            Path::new("data.bin"),
+            uploaded.as_slice(),
+            size as u64,
+            local.as_slice(),
+            size as u64,
+            false,
+            options,
+        );
+        assert_eq!(status, FileStatus::BinaryDiffers);
+        let status = compare_content(
+            Path::new("data.txt"),
+            vec![b'a'; size].as_slice(),
+            size as u64,
+            vec![b'b'; size].as_slice(),
+            size as u64,
+            false,
+            options,
+        );
+        assert_eq!(status, FileStatus::Differs);
+    }
+
+    /// A fresh directory for a test, unique to the test process
+    /// and removed again once the test is done
+    struct TestDirectory(cargo_metadata::camino::Utf8PathBuf);
+
+    impl std::ops::Deref for TestDirectory {
+        type Target = cargo_metadata::camino::Utf8Path;
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    impl Drop for TestDirectory {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn test_directory(name: &str) -> TestDirectory {
+        let directory = std::env::temp_dir().join(format!(
+            "cargo-safe-publish-test-{name}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        TestDirectory(cargo_metadata::camino::Utf8PathBuf::from_path_buf(directory).unwrap())
+    }
+
+    #[test]
+    fn inherited_include_patterns() {
+        let workspace_root = test_directory("inherited-include");
+        let package_root = workspace_root.join("member");
+        std::fs::create_dir_all(package_root.join("src")).unwrap();
+        std::fs::write(
+            workspace_root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"member\"]\n\n\
+             [workspace.package]\ninclude = [\"src/**\", \"Cargo.toml\"]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            package_root.join("Cargo.toml"),
+            "[package]\nname = \"member\"\nversion = \"0.1.0\"\ninclude.workspace = true\n",
+        )
+        .unwrap();
+        std::fs::write(package_root.join("src").join("lib.rs"), "").unwrap();
+
+        let manifest = read_package_manifest(&package_root, &workspace_root);
+        assert_eq!(
+            manifest.package.unwrap().include(),
+            ["src/**".to_owned(), "Cargo.toml".to_owned()]
+        );
+    }
+
+    /// A package named `pkg` with the version `0.1.0` at the given root
+    fn test_package(package_root: &cargo_metadata::camino::Utf8Path) -> cargo_metadata::Package {
+        serde_json::from_value(serde_json::json!({
+            "name": "pkg",
+            "version": "0.1.0",
+            "id": "path+file:///pkg#0.1.0",
+            "source": null,
+            "dependencies": [],
+            "targets": [],
+            "features": {},
+            "manifest_path": package_root.join("Cargo.toml"),
+        }))
+        .unwrap()
+    }
+
+    /// A gzip compressed tar archive with the given files, in the format of a `.crate` file
+    fn crate_archive(files: &[(&Path, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *content).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_archive_path() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let package_root = test_directory("non-utf8-path");
+        let file_name = std::ffi::OsStr::from_bytes(b"data-\xff.txt");
+        std::fs::write(package_root.join("Cargo.toml"), "").unwrap();
+        std::fs::write(package_root.as_std_path().join(file_name), "content").unwrap();
+        let archive = crate_archive(&[
+            (Path::new("pkg-0.1.0/Cargo.toml.orig"), b""),
+            (&Path::new("pkg-0.1.0").join(file_name), b"content"),
+        ]);
+
+        let report = verify_content_matches(
+            archive.as_slice(),
+            &LocalSource::WorkingTree,
+            &test_package(&package_root),
+            None,
+            &[],
+        )
+        .unwrap();
+        assert!(report.everything_matched());
+        let file = report
+            .files
+            .iter()
+            .find(|f| f.archive_path == Path::new(file_name))
+            .unwrap();
+        assert_eq!(file.status, FileStatus::Matches);
+    }
+
+    #[test]
+    fn stray_top_level_entry() {
+        let package_root = test_directory("stray-entry");
+        std::fs::create_dir_all(package_root.join("src")).unwrap();
+        std::fs::write(package_root.join("src").join("lib.rs"), "").unwrap();
+        let package = test_package(&package_root);
+        let verify = |entries: &[(&Path, &[u8])]| {
+            verify_content_matches(
+                crate_archive(entries).as_slice(),
+                &LocalSource::WorkingTree,
+                &package,
+                None,
+                &[],
+            )
+            .map(|report| report.everything_matched())
+        };
+
+        assert_eq!(
+            verify(&[(Path::new("pkg-0.1.0/src/lib.rs"), b"")]),
+            Ok(true)
+        );
+        for stray in ["stray.txt", "pkg-0.2.0/src/lib.rs"] {
+            assert_eq!(
+                verify(&[
+                    (Path::new("pkg-0.1.0/src/lib.rs"), b""),
+                    (Path::new(stray), b""),
+                ]),
+                Err(1),
+                "{stray}"
+            );
+        }
+        assert_eq!(
+            strip_top_level_directory(Path::new("pkg-0.1.0"), "pkg-0.1.0"),
+            None
+        );
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| (*a).to_owned()).collect()
+    }
+
+    #[test]
+    fn strip_invocation_via_cargo() {
+        let command_line = args(&[
+            "cargo-safe-publish",
+            "safe-publish",
+            "--package",
+            "safe-publish",
+        ]);
+        assert_eq!(
+            strip_invocation(&command_line),
+            args(&["--package", "safe-publish"])
+        );
+    }
+
+    #[test]
+    fn strip_invocation_of_the_binary() {
+        let command_line = args(&["cargo-safe-publish", "--package", "safe-publish"]);
+        assert_eq!(
+            strip_invocation(&command_line),
+            args(&["--package", "safe-publish"])
+        );
+    }
+}
@@ -0,0 +1,78 @@
+// A safer version of cargo publish
+//
+// Copyright (C) 2025 Georg Semmler
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see
+// <https://www.gnu.org/licenses/>.
+
+use colored::Colorize;
+
+use crate::APP_VERSION;
+use crate::config::{Notifications, WebhookFormat};
+
+const DEFAULT_MESSAGE: &str = "Published `{crate}` {version} to {registry}";
+
+/// Send the configured webhook notification about a successful publish
+///
+/// Failures are only reported as warnings as the crate is already published at this point
+pub(crate) fn send_webhook(
+    notifications: &Notifications,
+    package_name: &str,
+    package_version: &cargo_metadata::semver::Version,
+    registry: &str,
+    url: Option<&str>,
+    release_notes: Option<&str>,
+) {
+    let Some(webhook_url) = &notifications.webhook_url else {
+        return;
+    };
+    let mut message = notifications
+        .message
+        .as_deref()
+        .unwrap_or(DEFAULT_MESSAGE)
+        .replace("{crate}", package_name)
+        .replace("{version}", &package_version.to_string())
+        .replace("{registry}", registry)
+        .replace("{url}", url.unwrap_or_default())
+        .replace("{release_notes}", release_notes.unwrap_or_default());
+    if notifications.message.is_none()
+        && let Some(url) = url
+    {
+        message.push_str(&format!(" ({url})"));
+    }
+
+    let payload = match notifications.webhook_format.unwrap_or_default() {
+        WebhookFormat::Slack => serde_json::json!({ "text": message }),
+        WebhookFormat::Discord => serde_json::json!({ "content": message }),
+        WebhookFormat::Generic => serde_json::json!({
+            "crate": package_name,
+            "version": package_version.to_string(),
+            "registry": registry,
+            "url": url,
+            "message": message,
+            "release_notes": release_notes,
+        }),
+    };
+    let result = ureq::post(webhook_url)
+        .header("User-Agent", format!("cargo-safe-publish/{APP_VERSION}"))
+        .header("Content-Type", "application/json")
+        .send(payload.to_string());
+    match result {
+        Ok(_) => crate::print_status("Sent the publish notification"),
+        Err(e) => eprintln!(
+            "{}: failed to send the publish notification: {e}",
+            "warning".yellow()
+        ),
+    }
+}
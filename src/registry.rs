@@ -0,0 +1,732 @@
+// A safer version of cargo publish
+//
+// Copyright (C) 2025 Georg Semmler
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see
+// <https://www.gnu.org/licenses/>.
+
+use colored::Colorize;
+use std::path::PathBuf;
+
+use crate::APP_VERSION;
+
+pub(crate) const CRATES_IO: &str = "crates-io";
+const CRATES_IO_INDEX: &str = "https://index.crates.io/";
+const CRATES_IO_DOWNLOAD: &str = "https://crates.io/api/v1/crates";
+
+/// The registry a crate is published to
+#[derive(Debug)]
+pub(crate) struct Registry {
+    pub(crate) name: String,
+    /// The index url as written in the cargo configuration,
+    /// including the `sparse+` prefix for sparse registries
+    pub(crate) index: String,
+    token: Option<String>,
+    request_options: RequestOptions,
+    /// A local clone of the git index that is used instead of the live index
+    index_path: Option<PathBuf>,
+    /// Base urls that are tried in order if downloading from the registry fails
+    download_mirrors: Vec<String>,
+}
+
+/// Additional settings for all http requests to the registry
+#[derive(Debug, Default)]
+pub(crate) struct RequestOptions {
+    /// Replaces the default `cargo-safe-publish/{version}` user agent
+    pub(crate) user_agent: Option<String>,
+    /// Additional headers as name value pairs
+    pub(crate) headers: Vec<(String, String)>,
+    /// Ask http caches to revalidate all responses, set by `--refresh-index`
+    pub(crate) is_refresh_index: bool,
+}
+
+impl Registry {
+    /// Resolve the registry the same way `cargo publish` does
+    ///
+    /// An explicit `--registry` flag wins over `package.publish`
+    /// which wins over the `registry.default` config value
+    pub(crate) fn resolve(
+        registry_flag: Option<String>,
+        token_flag: Option<String>,
+        package_publish: Option<&[String]>,
+        request_options: RequestOptions,
+        index_path: Option<PathBuf>,
+        download_mirrors: Vec<String>,
+    ) -> Self {
+        let config = CargoConfig::load();
+        let name = registry_flag
+            .or_else(|| match package_publish {
+                Some([registry]) => Some(registry.clone()),
+                _ => None,
+            })
+            .or_else(|| std::env::var("CARGO_REGISTRY_DEFAULT").ok())
+            .or_else(|| config.get_str(&["registry", "default"]))
+            .unwrap_or_else(|| CRATES_IO.to_owned());
+
+        let index = if name == CRATES_IO {
+            format!("sparse+{CRATES_IO_INDEX}")
+        } else {
+            std::env::var(format!("CARGO_REGISTRIES_{}_INDEX", env_name(&name)))
+                .ok()
+                .or_else(|| config.get_str(&["registries", &name, "index"]))
+                .unwrap_or_else(|| {
+                    eprintln!(
+                        "{}: could not find the index url of the registry `{name}` in the cargo configuration",
+                        "error".red().bold()
+                    );
+                    std::process::exit(1);
+                })
+        };
+
+        let token = token_flag
+            .or_else(|| std::env::var(token_env_var(&name)).ok())
+            .or_else(|| {
+                let credentials = CargoConfig::load_credentials();
+                if name == CRATES_IO {
+                    credentials.get_str(&["registry", "token"])
+                } else {
+                    credentials.get_str(&["registries", &name, "token"])
+                }
+            });
+
+        Self {
+            name,
+            index,
+            token,
+            request_options,
+            index_path,
+            download_mirrors,
+        }
+    }
+
+    fn is_crates_io(&self) -> bool {
+        self.name == CRATES_IO
+    }
+
+    /// The url of the `.crate` file for the given crate version
+    pub(crate) fn download_url(
+        &self,
+        package_name: &str,
+        package_version: &cargo_metadata::semver::Version,
+    ) -> String {
+        if let Some(index_path) = &self.index_path {
+            let config_path = index_path.join("config.json");
+            let config = std::fs::read_to_string(&config_path)
+                .map_err(|e| e.to_string())
+                .and_then(|c| serde_json::from_str::<IndexConfig>(&c).map_err(|e| e.to_string()))
+                .unwrap_or_else(|e| {
+                    eprintln!(
+                        "{}: failed to read the index configuration `{}`: {e}",
+                        "error".red().bold(),
+                        config_path.display()
+                    );
+                    std::process::exit(1);
+                });
+            return self.expand_download_template(&config.dl, package_name, package_version);
+        }
+        if self.is_crates_io() {
+            return format!("{CRATES_IO_DOWNLOAD}/{package_name}/{package_version}/download");
+        }
+        let Some(sparse_index) = self.index.strip_prefix("sparse+") else {
+            eprintln!(
+                "{}: the registry `{}` uses a git index, pass a local clone of it via `--index-path`",
+                "error".red().bold(),
+                self.name
+            );
+            std::process::exit(1);
+        };
+        let config_url = format!("{}/config.json", sparse_index.trim_end_matches('/'));
+        let config = self
+            .get(&config_url)
+            .and_then(|mut r| r.body_mut().read_to_string())
+            .map(|body| serde_json::from_str::<IndexConfig>(&body));
+        let dl = match config {
+            Ok(Ok(config)) => config.dl,
+            Ok(Err(e)) => {
+                eprintln!(
+                    "{}: failed to parse the index configuration of the registry `{}`: {e}",
+                    "error".red().bold(),
+                    self.name
+                );
+                std::process::exit(1);
+            }
+            Err(e) => self.report_request_error(&config_url, e),
+        };
+        self.expand_download_template(&dl, package_name, package_version)
+    }
+
+    /// The `dl` template from the index configuration and whether the registry requires
+    /// authentication for downloads
+    ///
+    /// Unlike [`Registry::download_url`] this does not exit on errors, but describes them
+    pub(crate) fn download_template(&self) -> Result<(String, bool), String> {
+        if self.index_path.is_none() && self.is_crates_io() {
+            return Ok((
+                format!("{CRATES_IO_DOWNLOAD}/{{crate}}/{{version}}/download"),
+                false,
+            ));
+        }
+        let content = if let Some(index_path) = &self.index_path {
+            std::fs::read_to_string(index_path.join("config.json")).map_err(|e| e.to_string())?
+        } else {
+            let Some(sparse_index) = self.index.strip_prefix("sparse+") else {
+                return Err("git indices are only supported via `--index-path`".to_owned());
+            };
+            let config_url = format!("{}/config.json", sparse_index.trim_end_matches('/'));
+            match self.get(&config_url) {
+                Ok(mut response) => response
+                    .body_mut()
+                    .read_to_string()
+                    .map_err(|e| e.to_string())?,
+                Err(ureq::Error::StatusCode(401 | 403)) if self.token.is_none() => {
+                    return Err(
+                        "the registry requires authentication, but no token is configured"
+                            .to_owned(),
+                    );
+                }
+                Err(e) => return Err(format!("failed to fetch `{config_url}`: {e}")),
+            }
+        };
+        let config = serde_json::from_str::<IndexConfig>(&content).map_err(|e| e.to_string())?;
+        Ok((config.dl, config.auth_required))
+    }
+
+    /// Whether a token for this registry was passed or found in the environment
+    /// or the cargo credentials
+    pub(crate) fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// The source that replaces this registry via `source.<name>.replace-with`
+    /// in the cargo configuration
+    ///
+    /// `cargo safe-publish` always talks to the registry itself
+    pub(crate) fn replaced_with(&self) -> Option<String> {
+        CargoConfig::load().get_str(&["source", &self.name, "replace-with"])
+    }
+
+    /// The url of the web page of the given crate version, if the registry has one
+    pub(crate) fn crate_url(
+        &self,
+        package_name: &str,
+        package_version: &cargo_metadata::semver::Version,
+    ) -> Option<String> {
+        self.is_crates_io()
+            .then(|| format!("https://crates.io/crates/{package_name}/{package_version}"))
+    }
+
+    /// All versions of the given crate known to the registry index
+    ///
+    /// Returns an empty list if the crate was never published
+    pub(crate) fn published_versions(
+        &self,
+        package_name: &str,
+    ) -> Vec<cargo_metadata::semver::Version> {
+        self.index_entries(package_name)
+            .into_iter()
+            .map(|entry| entry.vers)
+            .collect()
+    }
+
+    /// All versions of the given crate known to the registry index,
+    /// together with whether they are yanked
+    pub(crate) fn published_versions_with_yanked(
+        &self,
+        package_name: &str,
+    ) -> Vec<(cargo_metadata::semver::Version, bool)> {
+        self.index_entries(package_name)
+            .into_iter()
+            .map(|entry| (entry.vers, entry.yanked))
+            .collect()
+    }
+
+    /// The names of the crates stored in the index file of the given crate name
+    ///
+    /// Index files are looked up case insensitive, so this might be a crate
+    /// whose name only differs by case
+    pub(crate) fn crate_names(&self, package_name: &str) -> Vec<String> {
+        let mut names = self
+            .index_entries(package_name)
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The sha256 checksum of the given crate version as recorded in the registry index
+    pub(crate) fn published_checksum(
+        &self,
+        package_name: &str,
+        package_version: &cargo_metadata::semver::Version,
+    ) -> Option<String> {
+        self.index_entries(package_name)
+            .into_iter()
+            .find(|entry| &entry.vers == package_version)
+            .map(|entry| entry.cksum)
+    }
+
+    /// Remove the entry of the given crate from cargo's sparse index cache
+    /// and fetch it again, bypassing any http cache
+    ///
+    /// Prints the etag or modification date of the fetched entry
+    pub(crate) fn refresh_index(&self, package_name: &str) {
+        if self.index_path.is_some() {
+            eprintln!(
+                "{}: `--refresh-index` has no effect on the local index clone passed via `--index-path`",
+                "warning".yellow()
+            );
+            return;
+        }
+        let Some(sparse_index) = self.index.strip_prefix("sparse+") else {
+            eprintln!(
+                "{}: `--refresh-index` only supports sparse registries, `{}` uses a git index",
+                "warning".yellow(),
+                self.name
+            );
+            return;
+        };
+        let path = index_file_path(package_name);
+
+        // cargo stores the cache of each index in a directory named `{host}-{hash}`
+        let host = sparse_index
+            .split("://")
+            .nth(1)
+            .and_then(|rest| rest.split(['/', ':']).next())
+            .unwrap_or_default();
+        let index_cache = cargo_home().map(|home| home.join("registry").join("index"));
+        let cache_directories = index_cache
+            .and_then(|index_cache| std::fs::read_dir(index_cache).ok())
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|name| name.strip_prefix(host))
+                    .is_some_and(|hash| hash.starts_with('-'))
+            });
+        for directory in cache_directories {
+            let cached = directory.path().join(".cache").join(&path);
+            match std::fs::remove_file(&cached) {
+                Ok(()) => crate::print_status(format_args!(
+                    "Removed the cached index entry `{}`",
+                    cached.display()
+                )),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    eprintln!(
+                        "{}: failed to remove the cached index entry `{}`: {e}",
+                        "warning".yellow(),
+                        cached.display()
+                    );
+                }
+            }
+        }
+
+        let url = format!("{}/{path}", sparse_index.trim_end_matches('/'));
+        let response = match self.get(&url) {
+            Ok(response) => response,
+            Err(ureq::Error::StatusCode(404 | 410 | 451)) => {
+                crate::print_status(format_args!(
+                    "The index has no entry for `{package_name}` yet"
+                ));
+                return;
+            }
+            Err(e) => self.report_request_error(&url, e),
+        };
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        match (header("etag"), header("last-modified")) {
+            (Some(etag), _) => crate::print_status(format_args!(
+                "Using the index entry of `{package_name}` with etag {etag}"
+            )),
+            (None, Some(date)) => crate::print_status(format_args!(
+                "Using the index entry of `{package_name}` last modified at {date}"
+            )),
+            (None, None) => crate::print_status(format_args!(
+                "Using the index entry of `{package_name}`, the registry reported no revision for it"
+            )),
+        }
+    }
+
+    fn index_entries(&self, package_name: &str) -> Vec<IndexEntry> {
+        let path = index_file_path(package_name);
+        if let Some(index_path) = &self.index_path {
+            let file = index_path.join(path);
+            return match std::fs::read_to_string(&file) {
+                Ok(content) => parse_index_file(&content),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => {
+                    eprintln!(
+                        "{}: failed to read the index file `{}`: {e}",
+                        "error".red().bold(),
+                        file.display()
+                    );
+                    std::process::exit(1);
+                }
+            };
+        }
+        let Some(sparse_index) = self.index.strip_prefix("sparse+") else {
+            eprintln!(
+                "{}: the registry `{}` uses a git index, pass a local clone of it via `--index-path`",
+                "error".red().bold(),
+                self.name
+            );
+            std::process::exit(1);
+        };
+        let url = format!("{}/{path}", sparse_index.trim_end_matches('/'));
+        let body = match self.get(&url) {
+            Ok(mut response) => response
+                .body_mut()
+                .read_to_string()
+                .unwrap_or_else(|e| self.report_request_error(&url, e)),
+            // registries answer with one of these codes for unknown crates
+            Err(ureq::Error::StatusCode(404 | 410 | 451)) => return Vec::new(),
+            Err(e) => self.report_request_error(&url, e),
+        };
+        parse_index_file(&body)
+    }
+
+    /// Download the given crate version from this registry
+    ///
+    /// Falls back to the download mirrors in order if the registry
+    /// is temporarily unavailable
+    pub(crate) fn download(
+        &self,
+        package_name: &str,
+        package_version: &cargo_metadata::semver::Version,
+    ) -> ureq::BodyReader<'static> {
+        let url = self.download_url(package_name, package_version);
+        let error = match self.get(&url) {
+            Ok(response) => return response.into_body().into_reader(),
+            Err(e) if is_retriable(&e) && !self.download_mirrors.is_empty() => e,
+            Err(e) => self.report_request_error(&url, e),
+        };
+        eprintln!(
+            "{}: failed to fetch `{url}`: {error}, trying the download mirrors",
+            "warning".yellow()
+        );
+        for mirror in &self.download_mirrors {
+            let mirror_url = self.expand_download_template(mirror, package_name, package_version);
+            // the token belongs to the registry, so never send it to a mirror
+            match self.request(&mirror_url).call() {
+                Ok(response) => {
+                    crate::print_status(format_args!(
+                        "Downloaded `{package_name}` ({package_version}) from the mirror `{mirror_url}`"
+                    ));
+                    return response.into_body().into_reader();
+                }
+                Err(e) => {
+                    eprintln!(
+                        "{}: failed to fetch `{mirror_url}`: {e}",
+                        "warning".yellow()
+                    );
+                }
+            }
+        }
+        eprintln!(
+            "{}: failed to download `{package_name}` ({package_version}) from the registry `{}` and all download mirrors",
+            "error".red().bold(),
+            self.name
+        );
+        std::process::exit(1);
+    }
+
+    /// Expand a `dl` template, looking up the checksum of the crate version
+    /// in the index if the template needs it
+    fn expand_download_template(
+        &self,
+        template: &str,
+        package_name: &str,
+        package_version: &cargo_metadata::semver::Version,
+    ) -> String {
+        let checksum = if template.contains("{sha256-checksum}") {
+            let Some(checksum) = self.published_checksum(package_name, package_version) else {
+                eprintln!(
+                    "{}: the download url `{template}` requires the checksum of `{package_name}` ({package_version}), \
+                     but the version is missing in the index of the registry `{}`",
+                    "error".red().bold(),
+                    self.name
+                );
+                std::process::exit(1);
+            };
+            Some(checksum)
+        } else {
+            None
+        };
+        expand_download_template(template, package_name, package_version, checksum.as_deref())
+    }
+
+    fn get(&self, url: &str) -> Result<ureq::http::Response<ureq::Body>, ureq::Error> {
+        let mut request = self.request(url);
+        // crates.io never requires authentication for downloads,
+        // so don't leak the token there
+        if let Some(token) = &self.token
+            && !self.is_crates_io()
+        {
+            request = request.header("Authorization", token);
+        }
+        request.call()
+    }
+
+    /// A get request with the configured user agent and headers, but without the token
+    fn request(&self, url: &str) -> ureq::RequestBuilder<ureq::typestate::WithoutBody> {
+        let user_agent = self
+            .request_options
+            .user_agent
+            .clone()
+            .unwrap_or_else(|| format!("cargo-safe-publish/{APP_VERSION}"));
+        let mut request = ureq::get(url).header("User-Agent", user_agent);
+        for (name, value) in &self.request_options.headers {
+            request = request.header(name, value);
+        }
+        if self.request_options.is_refresh_index {
+            request = request.header("Cache-Control", "no-cache");
+        }
+        request
+    }
+
+    fn report_request_error(&self, url: &str, error: ureq::Error) -> ! {
+        // the token is never sent to crates.io, which also answers with 403
+        // for crate files that are not available yet
+        match error {
+            ureq::Error::StatusCode(401 | 403) if self.is_crates_io() => {
+                eprintln!("{}: failed to fetch `{url}`: {error}", "error".red().bold());
+            }
+            ureq::Error::StatusCode(401 | 403) if self.token.is_none() => {
+                eprintln!(
+                    "{}: the registry `{}` requires authentication for downloads; \
+                     provide a token via --token or {}",
+                    "error".red().bold(),
+                    self.name,
+                    token_env_var(&self.name)
+                );
+            }
+            ureq::Error::StatusCode(code @ (401 | 403)) => {
+                eprintln!(
+                    "{}: the registry `{}` rejected the provided token (http status {code})",
+                    "error".red().bold(),
+                    self.name,
+                );
+            }
+            e => {
+                eprintln!("{}: failed to fetch `{url}`: {e}", "error".red().bold());
+            }
+        }
+        std::process::exit(1);
+    }
+}
+
+/// The names of all registries defined in the cargo configuration, including crates.io
+pub(crate) fn configured_registries() -> Vec<String> {
+    let mut names = CargoConfig::load()
+        .tables
+        .iter()
+        .filter_map(|table| table.get("registries")?.as_table())
+        .flat_map(|registries| registries.keys().cloned())
+        .chain([CRATES_IO.to_owned()])
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn env_name(registry: &str) -> String {
+    registry.to_uppercase().replace('-', "_")
+}
+
+fn token_env_var(registry: &str) -> String {
+    if registry == CRATES_IO {
+        "CARGO_REGISTRY_TOKEN".to_owned()
+    } else {
+        format!("CARGO_REGISTRIES_{}_TOKEN", env_name(registry))
+    }
+}
+
+/// Whether the request might succeed with a different server or at a later time
+fn is_retriable(error: &ureq::Error) -> bool {
+    matches!(
+        error,
+        ureq::Error::StatusCode(429 | 500..)
+            | ureq::Error::Io(_)
+            | ureq::Error::Timeout(_)
+            | ureq::Error::HostNotFound
+            | ureq::Error::ConnectionFailed
+            | ureq::Error::BodyStalled
+    )
+}
+
+/// Expand the `dl` template of a registry index configuration
+///
+/// `checksum` replaces the `{sha256-checksum}` marker and is required if the template contains it
+///
+/// See <https://doc.rust-lang.org/cargo/reference/registry-index.html#index-configuration>
+fn expand_download_template(
+    template: &str,
+    package_name: &str,
+    package_version: &cargo_metadata::semver::Version,
+    checksum: Option<&str>,
+) -> String {
+    const MARKERS: &[&str] = &[
+        "{crate}",
+        "{version}",
+        "{prefix}",
+        "{lowerprefix}",
+        "{sha256-checksum}",
+    ];
+    if !MARKERS.iter().any(|m| template.contains(m)) {
+        return format!(
+            "{}/{package_name}/{package_version}/download",
+            template.trim_end_matches('/')
+        );
+    }
+    let prefix = index_prefix(package_name);
+    template
+        .replace("{crate}", package_name)
+        .replace("{version}", &package_version.to_string())
+        .replace("{lowerprefix}", &prefix.to_lowercase())
+        .replace("{prefix}", &prefix)
+        .replace("{sha256-checksum}", checksum.unwrap_or_default())
+}
+
+/// The path of the index file of the given crate relative to the index root
+fn index_file_path(package_name: &str) -> String {
+    format!(
+        "{}/{}",
+        index_prefix(package_name).to_lowercase(),
+        package_name.to_lowercase()
+    )
+}
+
+/// The directory prefix used by cargo to shard crates in the index
+fn index_prefix(package_name: &str) -> String {
+    match package_name.len() {
+        1 => "1".to_owned(),
+        2 => "2".to_owned(),
+        3 => format!("3/{}", &package_name[..1]),
+        _ => format!("{}/{}", &package_name[..2], &package_name[2..4]),
+    }
+}
+
+/// A single line of an index file of a registry
+///
+/// See <https://doc.rust-lang.org/cargo/reference/registry-index.html#json-schema>
+#[derive(serde_derive::Deserialize)]
+struct IndexEntry {
+    name: String,
+    vers: cargo_metadata::semver::Version,
+    cksum: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+fn parse_index_file(content: &str) -> Vec<IndexEntry> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).expect("Failed to parse registry index entry"))
+        .collect()
+}
+
+/// The `config.json` file at the root of a registry index
+#[derive(serde_derive::Deserialize)]
+struct IndexConfig {
+    dl: String,
+    #[serde(default, rename = "auth-required")]
+    auth_required: bool,
+}
+
+/// The merged cargo configuration files that apply to the current directory
+struct CargoConfig {
+    /// Configuration tables ordered by precedence, highest first
+    tables: Vec<toml::Table>,
+}
+
+impl CargoConfig {
+    fn load() -> Self {
+        let mut paths = Vec::new();
+        if let Ok(cwd) = std::env::current_dir() {
+            for dir in cwd.ancestors() {
+                paths.push(dir.join(".cargo").join("config.toml"));
+                paths.push(dir.join(".cargo").join("config"));
+            }
+        }
+        if let Some(cargo_home) = cargo_home() {
+            paths.push(cargo_home.join("config.toml"));
+            paths.push(cargo_home.join("config"));
+        }
+        Self::from_paths(&paths)
+    }
+
+    fn load_credentials() -> Self {
+        let paths = cargo_home()
+            .map(|cargo_home| {
+                vec![
+                    cargo_home.join("credentials.toml"),
+                    cargo_home.join("credentials"),
+                ]
+            })
+            .unwrap_or_default();
+        Self::from_paths(&paths)
+    }
+
+    fn from_paths(paths: &[PathBuf]) -> Self {
+        let tables = paths
+            .iter()
+            .filter_map(|p| std::fs::read_to_string(p).ok())
+            .filter_map(|content| content.parse::<toml::Table>().ok())
+            .collect();
+        Self { tables }
+    }
+
+    fn get_str(&self, path: &[&str]) -> Option<String> {
+        self.tables.iter().find_map(|table| {
+            let (last, parents) = path.split_last()?;
+            let table = parents
+                .iter()
+                .try_fold(table, |table, key| table.get(*key)?.as_table())?;
+            table.get(*last)?.as_str().map(ToOwned::to_owned)
+        })
+    }
+}
+
+pub(crate) fn cargo_home() -> Option<PathBuf> {
+    home::cargo_home().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_checksum_marker() {
+        let version = cargo_metadata::semver::Version::new(1, 2, 3);
+        assert_eq!(
+            expand_download_template(
+                "https://dl.example.com/{lowerprefix}/{crate}/{sha256-checksum}",
+                "serde",
+                &version,
+                Some("abc123"),
+            ),
+            "https://dl.example.com/se/rd/serde/abc123"
+        );
+    }
+}
@@ -0,0 +1,409 @@
+// A safer version of cargo publish
+//
+// Copyright (C) 2025 Georg Semmler
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see
+// <https://www.gnu.org/licenses/>.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+/// The outcome of comparing a single file of the uploaded `.crate` archive
+/// with the local version of that file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileStatus {
+    Matches,
+    Differs,
+    Missing,
+    /// Missing locally, but reported as warning due to `--missing-as-warning`
+    MissingIgnored,
+    Generated,
+    /// Differs and at least one version is not valid UTF-8
+    BinaryDiffers,
+    /// Not compared as it is not selected via `--only` or `--only-files`
+    Excluded,
+}
+
+impl FileStatus {
+    pub(crate) fn is_failure(self) -> bool {
+        matches!(
+            self,
+            FileStatus::Differs | FileStatus::BinaryDiffers | FileStatus::Missing
+        )
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            FileStatus::Matches => "matches",
+            FileStatus::Differs => "differs",
+            FileStatus::Missing => "missing locally",
+            FileStatus::MissingIgnored => "missing locally (ignored)",
+            FileStatus::Generated => "generated by cargo",
+            FileStatus::BinaryDiffers => "binary file differs",
+            FileStatus::Excluded => "excluded via `--only`",
+        }
+    }
+
+    /// The short status used in the file summary
+    pub(crate) fn as_summary_str(self) -> &'static str {
+        match self {
+            FileStatus::Matches => "ok",
+            FileStatus::Differs => "differs",
+            FileStatus::Missing | FileStatus::MissingIgnored => "missing",
+            FileStatus::Generated => "skipped:generated",
+            FileStatus::BinaryDiffers => "binary-differs",
+            FileStatus::Excluded => "skipped:excluded",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct FileReport {
+    /// The local path the file was compared with
+    pub(crate) path: PathBuf,
+    /// The path of the file in the archive, without the `{name}-{version}` directory
+    pub(crate) archive_path: PathBuf,
+    pub(crate) status: FileStatus,
+    pub(crate) local_size: Option<u64>,
+    pub(crate) uploaded_size: u64,
+}
+
+impl FileReport {
+    /// Why the content of this file was not compared with its local version,
+    /// `None` if the content was compared
+    pub(crate) fn unverified_reason(&self) -> Option<String> {
+        match self.status {
+            FileStatus::Generated => Some("generated by cargo".to_owned()),
+            FileStatus::Missing | FileStatus::MissingIgnored => Some("missing locally".to_owned()),
+            FileStatus::Excluded => Some("excluded via `--only`".to_owned()),
+            FileStatus::Matches | FileStatus::Differs | FileStatus::BinaryDiffers
+                if self.path != self.archive_path =>
+            {
+                Some(format!("remapped, compared with `{}`", self.path.display()))
+            }
+            FileStatus::Matches | FileStatus::Differs | FileStatus::BinaryDiffers => None,
+        }
+    }
+
+    pub(crate) fn byte_delta(&self) -> Option<i64> {
+        self.local_size
+            .map(|local_size| self.uploaded_size as i64 - local_size as i64)
+    }
+}
+
+/// The result of verifying the uploaded crate against the local package
+#[derive(Debug)]
+pub(crate) struct VerificationReport {
+    pub(crate) package_name: String,
+    pub(crate) package_version: cargo_metadata::semver::Version,
+    /// The hex encoded sha256 checksum of the uploaded `.crate` file
+    pub(crate) checksum: String,
+    pub(crate) files: Vec<FileReport>,
+    /// The `Cargo.toml` as rewritten by cargo while packaging the crate
+    pub(crate) manifest: Option<String>,
+}
+
+impl VerificationReport {
+    pub(crate) fn everything_matched(&self) -> bool {
+        !self.files.iter().any(|f| f.status.is_failure())
+    }
+
+    pub(crate) fn to_github_markdown(&self) -> String {
+        let mut out = String::new();
+        let result = if self.everything_matched() {
+            ":white_check_mark: verified"
+        } else {
+            ":x: verification failed"
+        };
+        writeln!(
+            out,
+            "### `{}` {}: {result}",
+            self.package_name, self.package_version
+        )
+        .unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "| File | Status | Byte delta |").unwrap();
+        writeln!(out, "| ---- | ------ | ---------: |").unwrap();
+        for file in &self.files {
+            let icon = match file.status {
+                FileStatus::Matches => ":white_check_mark:",
+                FileStatus::Differs | FileStatus::BinaryDiffers | FileStatus::Missing => ":x:",
+                FileStatus::MissingIgnored => ":warning:",
+                FileStatus::Generated | FileStatus::Excluded => ":information_source:",
+            };
+            let delta = match file.byte_delta() {
+                Some(delta) => format!("{delta:+}"),
+                None => "-".to_owned(),
+            };
+            writeln!(
+                out,
+                "| `{}` | {icon} {} | {delta} |",
+                file.path.display(),
+                file.status.as_str()
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// The files of the report sorted by their path in the archive,
+    /// only the ones that did not match if `is_quiet` is set
+    fn summary_files(&self, is_quiet: bool) -> Vec<&FileReport> {
+        let mut files = self
+            .files
+            .iter()
+            .filter(|f| !is_quiet || f.status != FileStatus::Matches)
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| a.archive_path.cmp(&b.archive_path));
+        files
+    }
+
+    /// Render a table with the status of every file in the archive
+    pub(crate) fn to_summary_table(&self, is_quiet: bool) -> String {
+        let mut out = String::new();
+        let files = self.summary_files(is_quiet);
+        let width = files
+            .iter()
+            .map(|f| f.status.as_summary_str().len())
+            .chain(["Status".len()])
+            .max()
+            .unwrap_or_default();
+        writeln!(out, "  {:<width$}  Path", "Status").unwrap();
+        for file in files {
+            writeln!(
+                out,
+                "  {:<width$}  {}",
+                file.status.as_summary_str(),
+                file.archive_path.display()
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// Render the status of every file in the archive as JSON
+    pub(crate) fn to_summary_json(&self, is_quiet: bool) -> String {
+        let files = self
+            .summary_files(is_quiet)
+            .into_iter()
+            .map(|file| {
+                serde_json::json!({
+                    "path": file.archive_path.display().to_string(),
+                    "local_path": file.path.display().to_string(),
+                    "status": file.status.as_summary_str(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let summary = serde_json::json!({
+            "package": self.package_name,
+            "version": self.package_version.to_string(),
+            "checksum": self.checksum,
+            "verified": self.everything_matched(),
+            "files": files,
+        });
+        serde_json::to_string_pretty(&summary).expect("Serializing json never fails")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CheckResult {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+impl CheckResult {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            CheckResult::Passed => "passed",
+            CheckResult::Failed => "failed",
+            CheckResult::Skipped => "skipped",
+        }
+    }
+}
+
+/// The versions of the tools used for the verification build and the publish
+#[derive(Debug, Default)]
+pub(crate) struct ToolVersions {
+    /// The first line of `cargo --version --verbose`
+    pub(crate) cargo: Option<String>,
+    /// The output of `rustc --version`
+    pub(crate) rustc: Option<String>,
+}
+
+/// Everything we know about a single `cargo safe-publish` run
+#[derive(Debug)]
+pub(crate) struct PublishReport<'a> {
+    pub(crate) package_name: &'a str,
+    pub(crate) package_version: &'a cargo_metadata::semver::Version,
+    pub(crate) registry: &'a str,
+    pub(crate) repository: Option<&'a str>,
+    pub(crate) published_at: gix::date::Time,
+    pub(crate) finished_at: gix::date::Time,
+    pub(crate) git_commit: Option<String>,
+    pub(crate) checks: Vec<(&'static str, CheckResult)>,
+    pub(crate) verification: Option<&'a VerificationReport>,
+    pub(crate) tool_versions: &'a ToolVersions,
+    /// The web page of the published version, if the registry has one
+    pub(crate) crate_url: Option<String>,
+    /// The Markdown content of the file passed via `--release-notes`
+    pub(crate) release_notes: Option<&'a str>,
+}
+
+impl PublishReport<'_> {
+    /// Render the report as plain Markdown
+    ///
+    /// This intentionally does not use tables as not all
+    /// Markdown renderers support them
+    pub(crate) fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "# Publish report for `{}` {}",
+            self.package_name, self.package_version
+        )
+        .unwrap();
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "* Published at: {}",
+            self.published_at
+                .format(gix::date::time::format::ISO8601_STRICT)
+        )
+        .unwrap();
+        if let Some(crate_url) = &self.crate_url {
+            writeln!(out, "* Release: {crate_url}").unwrap();
+        }
+        match &self.git_commit {
+            Some(commit) => writeln!(out, "* Git commit: `{commit}`").unwrap(),
+            None => writeln!(out, "* Git commit: unknown").unwrap(),
+        }
+        if let Some(verification) = self.verification {
+            writeln!(out, "* Checksum (sha256): `{}`", verification.checksum).unwrap();
+        }
+        writeln!(
+            out,
+            "* Published with: cargo-safe-publish {}",
+            crate::APP_VERSION
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "* Cargo: {}",
+            self.tool_versions.cargo.as_deref().unwrap_or("unknown")
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "* Rustc: {}",
+            self.tool_versions.rustc.as_deref().unwrap_or("unknown")
+        )
+        .unwrap();
+        writeln!(out).unwrap();
+        if let Some(release_notes) = self.release_notes {
+            writeln!(out, "## Release notes").unwrap();
+            writeln!(out).unwrap();
+            writeln!(out, "{}", release_notes.trim_end()).unwrap();
+            writeln!(out).unwrap();
+        }
+        writeln!(out, "## Checks").unwrap();
+        writeln!(out).unwrap();
+        for (check, result) in &self.checks {
+            writeln!(out, "* {check}: {}", result.as_str()).unwrap();
+        }
+        writeln!(out).unwrap();
+        writeln!(out, "## Published files").unwrap();
+        writeln!(out).unwrap();
+        match self.verification {
+            Some(verification) => {
+                for file in &verification.files {
+                    writeln!(
+                        out,
+                        "* `{}` ({} bytes)",
+                        file.path.display(),
+                        file.uploaded_size
+                    )
+                    .unwrap();
+                }
+            }
+            None => {
+                writeln!(
+                    out,
+                    "The published files are unknown as the content verification was skipped"
+                )
+                .unwrap();
+            }
+        }
+        out
+    }
+
+    /// Render an [in-toto statement](https://github.com/in-toto/attestation/blob/main/spec/v1/statement.md)
+    /// with a [SLSA provenance](https://slsa.dev/spec/v1.0/provenance) predicate for the published crate
+    ///
+    /// Returns `None` if the checksum of the published crate is unknown
+    pub(crate) fn to_attestation(&self) -> Option<String> {
+        let verification = self.verification?;
+        let mut resolved_dependencies = Vec::new();
+        if let Some(git_commit) = &self.git_commit {
+            let mut dependency = serde_json::json!({ "digest": { "gitCommit": git_commit } });
+            if let Some(repository) = self.repository {
+                dependency["uri"] = format!("git+{repository}@{git_commit}").into();
+            }
+            resolved_dependencies.push(dependency);
+        }
+        let statement = serde_json::json!({
+            "_type": "https://in-toto.io/Statement/v1",
+            "subject": [{
+                "name": format!("{}-{}.crate", self.package_name, self.package_version),
+                "digest": { "sha256": verification.checksum },
+            }],
+            "predicateType": "https://slsa.dev/provenance/v1",
+            "predicate": {
+                "buildDefinition": {
+                    "buildType": "https://github.com/weiznich/cargo-safe-publish/publish/v1",
+                    "externalParameters": {
+                        "package": self.package_name,
+                        "version": self.package_version.to_string(),
+                        "registry": self.registry,
+                    },
+                    "internalParameters": {
+                        "os": std::env::consts::OS,
+                        "arch": std::env::consts::ARCH,
+                        "checks": self
+                            .checks
+                            .iter()
+                            .map(|(check, result)| (check.to_string(), result.as_str()))
+                            .collect::<std::collections::BTreeMap<_, _>>(),
+                    },
+                    "resolvedDependencies": resolved_dependencies,
+                },
+                "runDetails": {
+                    "builder": {
+                        "id": "https://github.com/weiznich/cargo-safe-publish",
+                        "version": {
+                            "cargo-safe-publish": crate::APP_VERSION,
+                            "cargo": self.tool_versions.cargo,
+                            "rustc": self.tool_versions.rustc,
+                        },
+                    },
+                    "metadata": {
+                        "startedOn": self.published_at.format(gix::date::time::format::ISO8601_STRICT),
+                        "finishedOn": self.finished_at.format(gix::date::time::format::ISO8601_STRICT),
+                    },
+                },
+            },
+        });
+        Some(serde_json::to_string_pretty(&statement).expect("Serializing json never fails"))
+    }
+}
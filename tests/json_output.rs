@@ -0,0 +1,37 @@
+use std::process::Command;
+
+#[test]
+fn json_output_is_a_single_document() {
+    let package_root =
+        std::env::temp_dir().join(format!("cargo-safe-publish-json-{}", std::process::id()));
+    std::fs::create_dir_all(package_root.join("src")).unwrap();
+    std::fs::write(
+        package_root.join("Cargo.toml"),
+        "[package]\nname = \"json-output\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\
+         license = \"MIT\"\ndescription = \"test\"\n\n[workspace]\n",
+    )
+    .unwrap();
+    std::fs::write(package_root.join("src").join("lib.rs"), "").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-safe-publish"))
+        .args([
+            "safe-publish",
+            "--dry-run",
+            "--allow-dirty",
+            "--no-network",
+            "--report-unverified-files",
+            "--output-format",
+            "json",
+        ])
+        .current_dir(&package_root)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&package_root).unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let report = serde_json::from_str::<serde_json::Value>(&stdout)
+        .unwrap_or_else(|e| panic!("stdout is not a single JSON document: {e}\n{stdout}"));
+    assert_eq!(report["verified"], true);
+}